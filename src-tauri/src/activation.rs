@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+/// How a recording shortcut starts and stops a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ActivationMode {
+    /// Push-to-talk: record while the chord is held.
+    #[default]
+    Hold,
+    /// Press to start, press again to stop.
+    Toggle,
+    /// A short tap latches recording on until the next press; a long hold
+    /// behaves like push-to-talk.
+    Hybrid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RecordingShortcut {
+    Recorder,
+    Clipboard,
+}
+
+/// Per-shortcut activation modes, stored under
+/// [`crate::settings::SHORTCUT_ACTIVATION_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ActivationSettings {
+    pub(crate) recorder: ActivationMode,
+    pub(crate) clipboard: ActivationMode,
+    /// In hybrid mode, releases sooner than this after the press count as a tap.
+    pub(crate) hybrid_tap_ms: u64,
+}
+
+impl Default for ActivationSettings {
+    fn default() -> Self {
+        Self {
+            recorder: ActivationMode::Hold,
+            clipboard: ActivationMode::Hold,
+            hybrid_tap_ms: 300,
+        }
+    }
+}
+
+impl ActivationSettings {
+    pub(crate) fn mode_for(&self, shortcut: RecordingShortcut) -> ActivationMode {
+        match shortcut {
+            RecordingShortcut::Recorder => self.recorder,
+            RecordingShortcut::Clipboard => self.clipboard,
+        }
+    }
+
+    pub(crate) fn tap_threshold(&self) -> Duration {
+        Duration::from_millis(self.hybrid_tap_ms)
+    }
+}
+
+/// Whether pressing the shortcut while recording ends the session. In hybrid
+/// mode a press can only arrive mid-session after a tap latched it on.
+fn press_stops_recording(mode: ActivationMode) -> bool {
+    matches!(mode, ActivationMode::Toggle | ActivationMode::Hybrid)
+}
+
+/// Whether releasing the shortcut `held_for` after the starting press ends the session.
+fn release_stops_recording(
+    mode: ActivationMode,
+    held_for: Duration,
+    tap_threshold: Duration,
+) -> bool {
+    match mode {
+        ActivationMode::Hold => true,
+        ActivationMode::Toggle => false,
+        ActivationMode::Hybrid => held_for >= tap_threshold,
+    }
+}
+
+/// A press of a recording shortcut, or its release `held_for` after the press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ShortcutEvent {
+    Press,
+    Release { held_for: Duration },
+}
+
+/// Whether `event` on `shortcut` ends the session `started_by` began in
+/// `mode`. Only the starting shortcut controls a session; the other one is
+/// ignored until it ends.
+pub(crate) fn stops_recording(
+    started_by: RecordingShortcut,
+    mode: ActivationMode,
+    shortcut: RecordingShortcut,
+    event: ShortcutEvent,
+    tap_threshold: Duration,
+) -> bool {
+    if shortcut != started_by {
+        return false;
+    }
+    match event {
+        ShortcutEvent::Press => press_stops_recording(mode),
+        ShortcutEvent::Release { held_for } => {
+            release_stops_recording(mode, held_for, tap_threshold)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcut_events_stop_recording_per_mode() {
+        use RecordingShortcut::{Clipboard, Recorder};
+
+        let threshold = ActivationSettings::default().tap_threshold();
+        let tap = ShortcutEvent::Release {
+            held_for: threshold - Duration::from_millis(1),
+        };
+        let hold = ShortcutEvent::Release {
+            held_for: threshold + Duration::from_millis(1),
+        };
+
+        // (shortcut that started the session, its mode, shortcut used,
+        //  press stops, release after a tap stops, release after a hold stops)
+        let cases = [
+            (Recorder, ActivationMode::Hold, Recorder, false, true, true),
+            (
+                Recorder,
+                ActivationMode::Toggle,
+                Recorder,
+                true,
+                false,
+                false,
+            ),
+            (
+                Recorder,
+                ActivationMode::Hybrid,
+                Recorder,
+                true,
+                false,
+                true,
+            ),
+            // The other shortcut never ends the session, whatever its own mode.
+            (
+                Recorder,
+                ActivationMode::Hold,
+                Clipboard,
+                false,
+                false,
+                false,
+            ),
+            (
+                Clipboard,
+                ActivationMode::Toggle,
+                Recorder,
+                false,
+                false,
+                false,
+            ),
+        ];
+        for (started_by, mode, shortcut, press, release_tap, release_hold) in cases {
+            let stops = |event| stops_recording(started_by, mode, shortcut, event, threshold);
+            let case = format!(
+                "{:?} session in {:?} mode, {:?}",
+                started_by, mode, shortcut
+            );
+            assert_eq!(stops(ShortcutEvent::Press), press, "{} press", case);
+            assert_eq!(
+                stops(tap),
+                release_tap,
+                "{} release below the threshold",
+                case
+            );
+            assert_eq!(
+                stops(hold),
+                release_hold,
+                "{} release above the threshold",
+                case
+            );
+        }
+    }
+}
@@ -1,90 +1,544 @@
-use crate::state::RecordingFlag;
+use crate::cue::CueGate;
+use crate::preroll::WarmMicrophone;
+use crate::sample_convert::{extend_pcm16, ToPcm16};
+use crate::vad;
 use cpal::traits::{DeviceTrait, StreamTrait};
-use crossbeam_channel::Sender;
-use std::sync::atomic::Ordering;
-use std::thread;
-use std::time::Duration;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
 
-const AUDIO_CHUNK_SIZE_SAMPLES: usize = 1024;
+/// Default capture ring capacity, as audio time at the device's native format.
+pub(crate) const DEFAULT_CAPTURE_BUFFER_MS: u64 = 2000;
+/// Conversion scratch space reserved up front so typical callbacks never allocate.
+const CALLBACK_SCRATCH_SAMPLES: usize = 16384;
+pub(crate) const LEVEL_REPORT_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Optional behaviour for a capture session.
+#[derive(Default)]
+pub(crate) struct CaptureOptions {
+    pub(crate) hands_free: Option<HandsFreeSettings>,
+    /// Pre-roll source; used only when it matches the session's device and format.
+    pub(crate) warm_microphone: Option<Arc<WarmMicrophone>>,
+    /// Holds back audio while the record-start cue plays. The cue is silenced
+    /// in any pre-roll prepended once the gate opens.
+    pub(crate) cue_gate: Option<CueGate>,
+    /// Set to when the first sample in the ring, pre-roll included, was captured.
+    pub(crate) captured_from: Arc<OnceLock<Instant>>,
+    /// Fixed hardware buffer size in frames; the backend default when unset.
+    pub(crate) buffer_frames: Option<u32>,
+    /// Records only this zero-based input, so the ring holds mono audio.
+    pub(crate) input_channel: Option<u16>,
+}
+
+/// Ends a capture session. The capture thread sleeps on it between level
+/// reports and wakes as soon as it is stopped.
+#[derive(Clone, Default)]
+pub(crate) struct StopSignal(Arc<StopState>);
+
+#[derive(Default)]
+struct StopState {
+    /// Read by the audio callback, which must not block on the lock.
+    stopped: AtomicBool,
+    lock: Mutex<()>,
+    wake: Condvar,
+}
+
+impl StopSignal {
+    pub(crate) fn stop(&self) {
+        let _guard = self.0.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.0.stopped.store(true, Ordering::SeqCst);
+        self.0.wake.notify_all();
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.0.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Waits up to `timeout` for the signal; returns whether it has stopped.
+    pub(crate) fn wait(&self, timeout: Duration) -> bool {
+        let guard = self.0.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let _guard = self
+            .0
+            .wake
+            .wait_timeout_while(guard, timeout, |_| !self.is_stopped())
+            .unwrap_or_else(PoisonError::into_inner);
+        self.is_stopped()
+    }
+}
+
+/// Everything a capture session recorded, handed over once the stream has
+/// closed and its last buffer is in `samples`.
+#[derive(Default)]
+pub(crate) struct Capture {
+    /// Interleaved samples at the captured format.
+    pub(crate) samples: Vec<i16>,
+    /// Samples lost because the ring was full.
+    pub(crate) dropped_samples: u64,
+    /// Size of that ring, for overflow reports.
+    pub(crate) capacity_samples: usize,
+    /// Why the stream ended early, if it did. Audio captured before the
+    /// failure is still in `samples`.
+    pub(crate) error: Option<String>,
+}
+
+/// Input level over the last report window, normalised to `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub(crate) struct AudioLevel {
+    pub(crate) rms: f32,
+    pub(crate) peak: f32,
+    pub(crate) elapsed_ms: u64,
+}
+
+/// Accumulates level statistics from the audio callback without locking; the
+/// recording loop drains it once per report window.
+#[derive(Default)]
+pub(crate) struct LevelMeter {
+    sum_squares: AtomicU64,
+    samples: AtomicU64,
+    peak: AtomicU64,
+}
+
+impl LevelMeter {
+    pub(crate) fn record(&self, data: &[i16]) {
+        let mut sum_squares = 0u64;
+        let mut peak = 0u64;
+        for &sample in data {
+            let magnitude = u64::from(sample.unsigned_abs());
+            sum_squares += magnitude * magnitude;
+            peak = peak.max(magnitude);
+        }
+        self.sum_squares.fetch_add(sum_squares, Ordering::Relaxed);
+        self.samples.fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.peak.fetch_max(peak, Ordering::Relaxed);
+    }
+
+    pub(crate) fn take(&self, elapsed: Duration) -> AudioLevel {
+        let sum_squares = self.sum_squares.swap(0, Ordering::Relaxed);
+        let samples = self.samples.swap(0, Ordering::Relaxed);
+        let peak = self.peak.swap(0, Ordering::Relaxed);
+        let full_scale = f64::from(i16::MAX);
+        let rms = if samples > 0 {
+            (sum_squares as f64 / samples as f64).sqrt() / full_scale
+        } else {
+            0.0
+        };
+
+        AudioLevel {
+            rms: rms.min(1.0) as f32,
+            peak: (peak as f64 / full_scale).min(1.0) as f32,
+            elapsed_ms: elapsed.as_millis() as u64,
+        }
+    }
+}
+
+/// Hands-free dictation: one shortcut press starts recording and the session
+/// ends by itself after trailing silence or at the maximum duration.
+/// Stored under [`crate::settings::HANDS_FREE_SETTINGS_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct HandsFreeSettings {
+    pub(crate) enabled: bool,
+    /// Silence after the last voiced buffer that ends the session.
+    pub(crate) silence_timeout_ms: u64,
+    /// How long to wait for the user to start speaking, counted from when
+    /// capture starts letting audio through.
+    pub(crate) initial_silence_timeout_ms: u64,
+    pub(crate) max_duration_secs: u64,
+    /// Callback buffers louder than this count as voice.
+    pub(crate) silence_threshold_dbfs: f32,
+}
+
+impl Default for HandsFreeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            silence_timeout_ms: 2000,
+            initial_silence_timeout_ms: 8000,
+            max_duration_secs: 300,
+            silence_threshold_dbfs: -45.0,
+        }
+    }
+}
+
+impl HandsFreeSettings {
+    /// Why the session should end `elapsed` into the stream, if it should.
+    /// `listening_since` is when the first buffer got past the start-cue gate
+    /// and `last_voice` when the latest voiced buffer arrived.
+    fn stop_reason(
+        &self,
+        elapsed: Duration,
+        listening_since: Option<Duration>,
+        last_voice: Option<Duration>,
+    ) -> Option<String> {
+        if elapsed >= Duration::from_secs(self.max_duration_secs) {
+            return Some("maximum duration reached".to_string());
+        }
+        match (last_voice, listening_since) {
+            (Some(last_voice), _) => {
+                let silence = elapsed.saturating_sub(last_voice);
+                (silence >= Duration::from_millis(self.silence_timeout_ms))
+                    .then(|| format!("{:?} of trailing silence", silence))
+            }
+            (None, Some(listening_since)) => {
+                let silence = elapsed.saturating_sub(listening_since);
+                (silence >= Duration::from_millis(self.initial_silence_timeout_ms))
+                    .then(|| format!("no speech in the first {:?}", silence))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Stream time stored in an [`AtomicU64`] before the moment it marks.
+const NOT_YET: u64 = u64::MAX;
+
+fn stream_time(ms: &AtomicU64) -> Option<Duration> {
+    match ms.load(Ordering::Relaxed) {
+        NOT_YET => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
+fn build_stream<T, F>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    error_sender: mpsc::Sender<cpal::StreamError>,
+    mut process_data: F,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + ToPcm16,
+    F: FnMut(&[i16]) + Send + 'static,
+{
+    let err_fn = move |err| {
+        eprintln!("an error occurred on the audio stream: {}", err);
+        let _ = error_sender.send(err);
+    };
+    let mut converted_data: Vec<i16> = Vec::with_capacity(CALLBACK_SCRATCH_SAMPLES);
+
+    device.build_input_stream(
+        stream_config,
+        move |data: &[T], _: &_| {
+            converted_data.clear();
+            extend_pcm16(&mut converted_data, data);
+            process_data(&converted_data);
+        },
+        err_fn,
+        None,
+    )
+}
+
+/// Opens an input stream in the device's native sample format and hands the
+/// callback its data as interleaved 16-bit PCM.
+pub(crate) fn build_input_stream<F>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+    error_sender: mpsc::Sender<cpal::StreamError>,
+    process_data: F,
+) -> Result<cpal::Stream, String>
+where
+    F: FnMut(&[i16]) + Send + 'static,
+{
+    match sample_format {
+        cpal::SampleFormat::I8 => {
+            build_stream::<i8, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::I16 => {
+            build_stream::<i16, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::I32 => {
+            build_stream::<i32, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::I64 => {
+            build_stream::<i64, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::U8 => {
+            build_stream::<u8, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::U16 => {
+            build_stream::<u16, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::U32 => {
+            build_stream::<u32, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::U64 => {
+            build_stream::<u64, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::F32 => {
+            build_stream::<f32, _>(device, stream_config, error_sender, process_data)
+        }
+        cpal::SampleFormat::F64 => {
+            build_stream::<f64, _>(device, stream_config, error_sender, process_data)
+        }
+        _ => return Err(format!("Unsupported sample format: {:?}", sample_format)),
+    }
+    .map_err(|e| format!("Could not build input stream: {}", e))
+}
+
+fn describe_stream_error(err: &cpal::StreamError) -> String {
+    match err {
+        cpal::StreamError::DeviceNotAvailable => {
+            "The input device was disconnected during recording".to_string()
+        }
+        cpal::StreamError::BackendSpecific { err } => format!("Audio stream failed: {}", err),
+    }
+}
+
+/// Creates the lock-free ring the audio callback writes captured samples into.
+fn capture_buffer(capacity_samples: usize) -> (rtrb::Producer<i16>, rtrb::Consumer<i16>) {
+    rtrb::RingBuffer::new(capacity_samples.max(1))
+}
+
+/// Ring capacity holding `buffer_ms` of audio at `sample_rate` and `channels`.
+pub(crate) fn capture_buffer_samples(sample_rate: u32, channels: u16, buffer_ms: u64) -> usize {
+    (u64::from(sample_rate) * u64::from(channels) * buffer_ms / 1000) as usize
+}
+
+/// Writes as much of `data` as fits; returns the number of samples dropped.
+pub(crate) fn push_samples(producer: &mut rtrb::Producer<i16>, data: &[i16]) -> usize {
+    let writable = data.len().min(producer.slots());
+    let written = match producer.write_chunk_uninit(writable) {
+        Ok(chunk) => chunk.fill_from_iter(data.iter().copied()),
+        Err(_) => 0,
+    };
+    data.len() - written
+}
+
+/// Moves everything currently in the ring into `output`.
+fn drain_samples(consumer: &mut rtrb::Consumer<i16>, output: &mut Vec<i16>) {
+    let Ok(chunk) = consumer.read_chunk(consumer.slots()) else {
+        return;
+    };
+    let (first, second) = chunk.as_slices();
+    output.extend_from_slice(first);
+    output.extend_from_slice(second);
+    chunk.commit_all();
+}
+
+/// Records from `device` until `stop` fires, hands-free ends the session or
+/// the stream fails. The audio callback writes into a ring of
+/// `capacity_samples`, which this thread empties between level reports,
+/// passing each newly drained stretch of samples to `on_audio`.
 pub(crate) fn record_audio_stream(
-    recording_flag: RecordingFlag,
-    data_sender: Sender<Vec<i16>>,
+    stop: StopSignal,
+    capacity_samples: usize,
     device: cpal::Device,
     config: cpal::SupportedStreamConfig,
-    sample_format: cpal::SampleFormat,
-) -> Result<(), String> {
-    let err_fn = |err| eprintln!("an error occurred on the audio stream: {}", err);
-    let stream_config: cpal::StreamConfig = config.into();
-    let flag_clone = recording_flag.clone();
+    options: CaptureOptions,
+    mut on_level: impl FnMut(AudioLevel),
+    mut on_audio: impl FnMut(&[i16]),
+) -> Result<Capture, String> {
+    let CaptureOptions {
+        hands_free,
+        warm_microphone,
+        cue_gate,
+        captured_from,
+        buffer_frames,
+        input_channel,
+    } = options;
+    if stop.is_stopped() {
+        println!("Recording stopped before the stream opened.");
+        return Ok(Capture::default());
+    }
+    let device_name = device.name().unwrap_or_default();
+    let sample_format = config.sample_format();
+    let mut stream_config: cpal::StreamConfig = config.into();
+    if let Some(frames) = buffer_frames {
+        stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+    }
+    let stream_channels = usize::from(stream_config.channels.max(1));
+    let captured_channels = if input_channel.is_some() {
+        1
+    } else {
+        stream_config.channels.max(1)
+    };
+    let mut selected_channel: Vec<i16> = Vec::with_capacity(CALLBACK_SCRATCH_SAMPLES);
+    let (mut producer, mut consumer) = capture_buffer(capacity_samples);
+    let overflow_samples = Arc::new(AtomicU64::new(0));
+    let overflow_samples_callback = overflow_samples.clone();
+    let stop_callback = stop.clone();
+    let started_at = Instant::now();
+    let listening_since_ms = Arc::new(AtomicU64::new(NOT_YET));
+    let listening_since_callback = listening_since_ms.clone();
+    let last_voice_ms = Arc::new(AtomicU64::new(NOT_YET));
+    let last_voice_callback = last_voice_ms.clone();
+    let voice_threshold_dbfs = hands_free.as_ref().map(|h| h.silence_threshold_dbfs);
+    let level_meter = Arc::new(LevelMeter::default());
+    let level_meter_callback = level_meter.clone();
+    let mut pending_preroll =
+        warm_microphone.filter(|warm| warm.matches(&device_name, &stream_config, input_channel));
+    if pending_preroll.is_some() {
+        println!("Pre-roll from warm microphone will be prepended.");
+    }
 
     println!(
-        "Audio Stream: {} Hz, {} ch, {:?}",
-        stream_config.sample_rate.0, stream_config.channels, sample_format
+        "Audio Stream: {} Hz, {} ch, {:?}, buffer {:?}, input {:?}",
+        stream_config.sample_rate.0,
+        stream_config.channels,
+        sample_format,
+        stream_config.buffer_size,
+        input_channel.map(|channel| channel + 1)
     );
 
+    let sample_rate = stream_config.sample_rate.0;
+    let samples_per_second = f64::from(sample_rate) * f64::from(captured_channels);
     let process_data = move |data: &[i16]| {
-        if flag_clone.load(Ordering::SeqCst) {
-            for chunk in data.chunks(AUDIO_CHUNK_SIZE_SAMPLES) {
-                match data_sender.send(chunk.to_vec()) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("Audio thread failed to send data: {}. Stopping.", e);
-                        flag_clone.store(false, Ordering::SeqCst);
-                        break;
-                    }
+        if !stop_callback.is_stopped() {
+            let now = Instant::now();
+            if cue_gate.as_ref().is_some_and(|gate| !gate.is_open(now)) {
+                return;
+            }
+            let data = match input_channel {
+                Some(channel) => {
+                    selected_channel.clear();
+                    selected_channel.extend(
+                        data.iter()
+                            .skip(usize::from(channel))
+                            .step_by(stream_channels),
+                    );
+                    &selected_channel[..]
                 }
+                None => data,
+            };
+            level_meter_callback.record(data);
+            if let Some(threshold) = voice_threshold_dbfs {
+                let elapsed_ms = started_at.elapsed().as_millis() as u64;
+                if listening_since_callback.load(Ordering::Relaxed) == NOT_YET {
+                    listening_since_callback.store(elapsed_ms, Ordering::Relaxed);
+                }
+                if vad::frame_dbfs(data) >= threshold {
+                    last_voice_callback.store(elapsed_ms, Ordering::Relaxed);
+                }
+            }
+            let mut dropped = 0;
+            let mut preroll_samples = 0;
+            if let Some(warm) = pending_preroll.take() {
+                // Both streams saw this buffer; keep only the session's copy.
+                // The pre-roll reaches back before a start-cue gate opened, so
+                // the cue inside it is written as silence.
+                let preroll_ends_at = now
+                    .checked_sub(Duration::from_secs_f64(
+                        data.len() as f64 / samples_per_second,
+                    ))
+                    .unwrap_or(now);
+                let cue = |len| {
+                    cue_gate.as_ref().map_or(0..0, |gate| {
+                        gate.cue_range(preroll_ends_at, sample_rate, captured_channels, len)
+                    })
+                };
+                (preroll_samples, dropped) = warm.write_preroll(&mut producer, data.len(), cue);
+            }
+            captured_from.get_or_init(|| {
+                let buffered = (preroll_samples + data.len()) as f64 / samples_per_second;
+                now.checked_sub(Duration::from_secs_f64(buffered))
+                    .unwrap_or(now)
+            });
+            dropped += push_samples(&mut producer, data);
+            if dropped > 0 {
+                overflow_samples_callback.fetch_add(dropped as u64, Ordering::Relaxed);
             }
         }
     };
 
-    let stream = match sample_format {
-        cpal::SampleFormat::I16 => device.build_input_stream(
-            &stream_config,
-            move |data: &[i16], _: &_| process_data(data),
-            err_fn,
-            None,
-        ),
-        cpal::SampleFormat::U16 => device.build_input_stream(
-            &stream_config,
-            move |data: &[u16], _: &_| {
-                let converted_data: Vec<i16> = data
-                    .iter()
-                    .map(|&s| s.saturating_sub(32768) as i16)
-                    .collect();
-                process_data(&converted_data);
-            },
-            err_fn,
-            None,
-        ),
-        cpal::SampleFormat::F32 => device.build_input_stream(
-            &stream_config,
-            move |data: &[f32], _: &_| {
-                let converted_data: Vec<i16> = data
-                    .iter()
-                    .map(|&s| (s.clamp(-1.0, 1.0) * 32767.0) as i16)
-                    .collect();
-                process_data(&converted_data);
-            },
-            err_fn,
-            None,
-        ),
-        _ => return Err(format!("Unsupported sample format: {:?}", sample_format)),
-    }
-    .map_err(|e| format!("Could not build input stream: {}", e))?;
+    let (error_sender, stream_errors) = mpsc::channel();
+    let stream = build_input_stream(
+        &device,
+        &stream_config,
+        sample_format,
+        error_sender,
+        process_data,
+    )?;
 
     stream
         .play()
         .map_err(|e| format!("Could not start stream: {}", e))?;
-    println!("Recording stream started. Sending data via channel.");
+    println!("Recording stream started. Writing data to capture buffer.");
 
-    while recording_flag.load(Ordering::SeqCst) {
-        thread::sleep(Duration::from_millis(50));
+    let mut samples = Vec::new();
+    let mut error = None;
+    while !stop.wait(LEVEL_REPORT_INTERVAL) {
+        let drained_from = samples.len();
+        drain_samples(&mut consumer, &mut samples);
+        if samples.len() > drained_from {
+            on_audio(&samples[drained_from..]);
+        }
+
+        if let Ok(err) = stream_errors.try_recv() {
+            // End the session but keep what was captured for processing.
+            error = Some(describe_stream_error(&err));
+            stop.stop();
+            break;
+        }
+
+        on_level(level_meter.take(started_at.elapsed()));
+
+        if let Some(hands_free) = &hands_free {
+            let reason = hands_free.stop_reason(
+                started_at.elapsed(),
+                stream_time(&listening_since_ms),
+                stream_time(&last_voice_ms),
+            );
+            if let Some(reason) = reason {
+                println!("Hands-free: {}, stopping.", reason);
+                stop.stop();
+            }
+        }
     }
 
-    println!("Recording stream stopping (flag turned false)...");
+    println!("Recording stream stopping...");
+    // Dropping the stream waits out any running callback, so the ring holds
+    // the last buffer once it returns.
     drop(stream);
+    let drained_from = samples.len();
+    drain_samples(&mut consumer, &mut samples);
+    if samples.len() > drained_from {
+        on_audio(&samples[drained_from..]);
+    }
     println!("Recording stream stopped.");
 
-    Ok(())
+    Ok(Capture {
+        samples,
+        dropped_samples: overflow_samples.load(Ordering::Relaxed),
+        capacity_samples,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_free_waits_for_speech_before_timing_silence() {
+        let settings = HandsFreeSettings {
+            enabled: true,
+            ..HandsFreeSettings::default()
+        };
+        let secs = Duration::from_secs_f64;
+
+        // The start cue holds capture back for about a second; the user then
+        // takes a few seconds to start speaking.
+        assert_eq!(settings.stop_reason(secs(1.0), None, None), None);
+        assert_eq!(settings.stop_reason(secs(4.0), Some(secs(1.1)), None), None);
+        assert!(settings
+            .stop_reason(secs(9.2), Some(secs(1.1)), None)
+            .unwrap()
+            .starts_with("no speech"));
+
+        // Once speech has been heard, only trailing silence counts.
+        assert_eq!(
+            settings.stop_reason(secs(5.0), Some(secs(1.1)), Some(secs(4.0))),
+            None
+        );
+        assert!(settings
+            .stop_reason(secs(6.0), Some(secs(1.1)), Some(secs(4.0)))
+            .unwrap()
+            .contains("trailing silence"));
+
+        assert_eq!(
+            settings.stop_reason(secs(300.0), Some(secs(1.1)), Some(secs(299.9))),
+            Some("maximum duration reached".to_string())
+        );
+    }
 }
@@ -0,0 +1,328 @@
+use crate::devices::InputDevice;
+use crate::settings;
+use cpal::traits::DeviceTrait;
+use std::collections::HashMap;
+use tauri::Emitter;
+
+const EVENT_CAPTURE_FORMAT_INVALID: &str = "capture_format_invalid";
+
+/// A user's capture format choice for one device. Unset fields follow the
+/// device default. Saved per stable device ID under
+/// [`crate::settings::CAPTURE_FORMAT_KEY`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct CaptureFormat {
+    pub(crate) sample_rate: Option<u32>,
+    /// Frames per hardware buffer.
+    pub(crate) buffer_frames: Option<u32>,
+    /// Zero-based input to record on its own, e.g. `1` for input 2 of an
+    /// interface. `None` records every channel and downmixes them.
+    pub(crate) input_channel: Option<u16>,
+}
+
+impl CaptureFormat {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub(crate) struct CaptureFormatRange {
+    pub(crate) channels: u16,
+    pub(crate) min_sample_rate: u32,
+    pub(crate) max_sample_rate: u32,
+    /// `None` when the backend cannot report its buffer size limits.
+    pub(crate) min_buffer_frames: Option<u32>,
+    pub(crate) max_buffer_frames: Option<u32>,
+    pub(crate) sample_format: String,
+}
+
+/// What a device can capture, for the settings UI.
+#[derive(Debug, Clone, serde::Serialize)]
+pub(crate) struct SupportedCaptureFormats {
+    pub(crate) default_sample_rate: u32,
+    pub(crate) default_channels: u16,
+    pub(crate) ranges: Vec<CaptureFormatRange>,
+}
+
+/// The stream configuration a session opens, with the options that cpal's
+/// config does not carry.
+pub(crate) struct SessionCapture {
+    pub(crate) config: cpal::SupportedStreamConfig,
+    pub(crate) buffer_frames: Option<u32>,
+    pub(crate) input_channel: Option<u16>,
+}
+
+impl SessionCapture {
+    /// Channels that reach the capture buffer after input selection.
+    pub(crate) fn captured_channels(&self) -> u16 {
+        if self.input_channel.is_some() {
+            1
+        } else {
+            self.config.channels()
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct CaptureFormatInvalid {
+    device: String,
+    format: CaptureFormat,
+    reason: String,
+}
+
+fn buffer_limits(buffer_size: &cpal::SupportedBufferSize) -> (Option<u32>, Option<u32>) {
+    match buffer_size {
+        cpal::SupportedBufferSize::Range { min, max } => (Some(*min), Some(*max)),
+        cpal::SupportedBufferSize::Unknown => (None, None),
+    }
+}
+
+pub(crate) fn supported_formats(device: &cpal::Device) -> Result<SupportedCaptureFormats, String> {
+    let default = device
+        .default_input_config()
+        .map_err(|e| format!("Error getting default input config: {}", e))?;
+    let ranges = device
+        .supported_input_configs()
+        .map_err(|e| format!("Error getting supported input configs: {}", e))?
+        .map(|range| {
+            let (min_buffer_frames, max_buffer_frames) = buffer_limits(range.buffer_size());
+            CaptureFormatRange {
+                channels: range.channels(),
+                min_sample_rate: range.min_sample_rate().0,
+                max_sample_rate: range.max_sample_rate().0,
+                min_buffer_frames,
+                max_buffer_frames,
+                sample_format: range.sample_format().to_string(),
+            }
+        })
+        .collect();
+
+    Ok(SupportedCaptureFormats {
+        default_sample_rate: default.sample_rate().0,
+        default_channels: default.channels(),
+        ranges,
+    })
+}
+
+/// Picks the supported configuration that satisfies `format`. Configurations
+/// in the default sample format are preferred, then the fewest channels that
+/// still include the requested input.
+fn choose_config(
+    ranges: Vec<cpal::SupportedStreamConfigRange>,
+    default: &cpal::SupportedStreamConfig,
+    format: &CaptureFormat,
+) -> Result<cpal::SupportedStreamConfig, String> {
+    let sample_rate = format.sample_rate.unwrap_or(default.sample_rate().0);
+    let fits = |range: &cpal::SupportedStreamConfigRange| {
+        let channels_fit = match format.input_channel {
+            Some(channel) => channel < range.channels(),
+            None => range.channels() == default.channels(),
+        };
+        let buffer_fits = match (format.buffer_frames, buffer_limits(range.buffer_size())) {
+            (Some(frames), (Some(min), Some(max))) => (min..=max).contains(&frames),
+            _ => true,
+        };
+        channels_fit
+            && buffer_fits
+            && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&sample_rate)
+    };
+
+    ranges
+        .into_iter()
+        .filter(fits)
+        .min_by_key(|range| {
+            (
+                range.sample_format() != default.sample_format(),
+                range.channels(),
+            )
+        })
+        .map(|range| range.with_sample_rate(cpal::SampleRate(sample_rate)))
+        .ok_or_else(|| {
+            let mut wanted = vec![format!("{} Hz", sample_rate)];
+            if let Some(frames) = format.buffer_frames {
+                wanted.push(format!("{}-frame buffer", frames));
+            }
+            if let Some(channel) = format.input_channel {
+                wanted.push(format!("input {}", channel + 1));
+            }
+            format!("Device does not support {}", wanted.join(", "))
+        })
+}
+
+/// Resolves `format` against what `device` supports right now.
+pub(crate) fn resolve(
+    device: &cpal::Device,
+    format: &CaptureFormat,
+) -> Result<SessionCapture, String> {
+    let default = device
+        .default_input_config()
+        .map_err(|e| format!("Error getting default input config: {}", e))?;
+    if format.is_default() {
+        return Ok(SessionCapture {
+            config: default,
+            buffer_frames: None,
+            input_channel: None,
+        });
+    }
+
+    let ranges = device
+        .supported_input_configs()
+        .map_err(|e| format!("Error getting supported input configs: {}", e))?
+        .collect();
+    Ok(SessionCapture {
+        config: choose_config(ranges, &default, format)?,
+        buffer_frames: format.buffer_frames,
+        input_channel: format.input_channel,
+    })
+}
+
+pub(crate) fn saved_format(app_handle: &tauri::AppHandle, device_id: &str) -> CaptureFormat {
+    let formats: HashMap<String, CaptureFormat> =
+        settings::read(app_handle, settings::CAPTURE_FORMAT_KEY).unwrap_or_default();
+    formats.get(device_id).cloned().unwrap_or_default()
+}
+
+/// Validates `format` against the connected device and saves it.
+pub(crate) fn save_format(
+    app_handle: &tauri::AppHandle,
+    input: &InputDevice,
+    format: CaptureFormat,
+) -> Result<(), String> {
+    resolve(&input.device, &format)?;
+
+    let mut formats: HashMap<String, CaptureFormat> =
+        settings::read(app_handle, settings::CAPTURE_FORMAT_KEY).unwrap_or_default();
+    if format.is_default() {
+        formats.remove(&input.id);
+    } else {
+        formats.insert(input.id.clone(), format);
+    }
+    settings::write(app_handle, settings::CAPTURE_FORMAT_KEY, &formats)
+}
+
+/// The configuration for a new session on `input`. A saved format the device
+/// no longer supports is reported and replaced by the device default.
+pub(crate) fn resolve_for_session(
+    app_handle: &tauri::AppHandle,
+    input: &InputDevice,
+) -> Result<SessionCapture, String> {
+    let format = saved_format(app_handle, &input.id);
+    match resolve(&input.device, &format) {
+        Ok(capture) => Ok(capture),
+        Err(reason) if !format.is_default() => {
+            eprintln!(
+                "Saved capture format for '{}' is no longer valid ({}), using device default",
+                input.name, reason
+            );
+            let payload = CaptureFormatInvalid {
+                device: input.id.clone(),
+                format,
+                reason,
+            };
+            if let Err(e) = app_handle.emit(EVENT_CAPTURE_FORMAT_INVALID, &payload) {
+                eprintln!(
+                    "Failed to emit {} event: {}",
+                    EVENT_CAPTURE_FORMAT_INVALID, e
+                );
+            }
+            resolve(&input.device, &CaptureFormat::default())
+        }
+        Err(reason) => Err(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+
+    const BUFFER: SupportedBufferSize = SupportedBufferSize::Range { min: 64, max: 4096 };
+
+    /// A four-input interface defaulting to 96 kHz f32 on all inputs.
+    fn interface() -> (Vec<SupportedStreamConfigRange>, cpal::SupportedStreamConfig) {
+        let ranges = vec![
+            SupportedStreamConfigRange::new(
+                4,
+                SampleRate(44_100),
+                SampleRate(96_000),
+                BUFFER,
+                SampleFormat::F32,
+            ),
+            SupportedStreamConfigRange::new(
+                2,
+                SampleRate(44_100),
+                SampleRate(96_000),
+                BUFFER,
+                SampleFormat::F32,
+            ),
+            SupportedStreamConfigRange::new(
+                2,
+                SampleRate(44_100),
+                SampleRate(48_000),
+                BUFFER,
+                SampleFormat::I16,
+            ),
+        ];
+        let default =
+            cpal::SupportedStreamConfig::new(4, SampleRate(96_000), BUFFER, SampleFormat::F32);
+        (ranges, default)
+    }
+
+    #[test]
+    fn sample_rate_override_keeps_default_channels() {
+        let (ranges, default) = interface();
+        let format = CaptureFormat {
+            sample_rate: Some(48_000),
+            ..CaptureFormat::default()
+        };
+        let config = choose_config(ranges, &default, &format).unwrap();
+        assert_eq!(config.sample_rate(), SampleRate(48_000));
+        assert_eq!(config.channels(), 4);
+        assert_eq!(config.sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn input_channel_picks_fewest_channels_containing_it() {
+        let (ranges, default) = interface();
+        let second = CaptureFormat {
+            input_channel: Some(1),
+            ..CaptureFormat::default()
+        };
+        let fourth = CaptureFormat {
+            input_channel: Some(3),
+            ..CaptureFormat::default()
+        };
+        assert_eq!(
+            choose_config(ranges.clone(), &default, &second)
+                .unwrap()
+                .channels(),
+            2
+        );
+        assert_eq!(
+            choose_config(ranges, &default, &fourth).unwrap().channels(),
+            4
+        );
+    }
+
+    #[test]
+    fn unsupported_choices_are_rejected() {
+        let (ranges, default) = interface();
+        let too_fast = CaptureFormat {
+            sample_rate: Some(192_000),
+            ..CaptureFormat::default()
+        };
+        let huge_buffer = CaptureFormat {
+            buffer_frames: Some(8192),
+            ..CaptureFormat::default()
+        };
+        let missing_input = CaptureFormat {
+            input_channel: Some(4),
+            ..CaptureFormat::default()
+        };
+        assert!(choose_config(ranges.clone(), &default, &too_fast).is_err());
+        assert!(choose_config(ranges.clone(), &default, &huge_buffer).is_err());
+        let error = choose_config(ranges, &default, &missing_input).unwrap_err();
+        assert!(error.contains("input 5"), "{}", error);
+    }
+}
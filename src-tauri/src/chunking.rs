@@ -0,0 +1,280 @@
+//! Splits recordings too large for one transcription request into chunks cut
+//! at pauses, and joins the chunk transcripts back into one.
+
+use crate::transcription::{TranscriptSegment, TranscriptionResponse};
+use crate::vad;
+use crate::wav::{self, WavPcm};
+
+/// Size of a WAV header as written by [`wav::write_pcm16`].
+const WAV_HEADER_BYTES: usize = 44;
+/// Analysis frame used to find pauses.
+const FRAME_MS: usize = 20;
+/// A cut goes in the middle of the quietest stretch this long.
+const PAUSE_MS: usize = 300;
+
+/// Stored under [`crate::settings::TRANSCRIPTION_CHUNKING_KEY`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ChunkingSettings {
+    /// Largest WAV sent in one request. The default stays under the 25 MB
+    /// upload limit common to OpenAI-compatible APIs.
+    pub(crate) max_chunk_bytes: usize,
+    /// Chunks transcribed at the same time.
+    pub(crate) max_parallel: usize,
+    /// How far back from the size limit a chunk may end to land on a pause.
+    pub(crate) search_window_ms: u32,
+}
+
+impl Default for ChunkingSettings {
+    fn default() -> Self {
+        Self {
+            max_chunk_bytes: 24 * 1024 * 1024,
+            max_parallel: 3,
+            search_window_ms: 30_000,
+        }
+    }
+}
+
+/// A piece of a longer recording, ready to transcribe.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AudioChunk {
+    /// Where the chunk starts in the recording.
+    pub(crate) start_ms: u64,
+    pub(crate) duration_ms: u64,
+    pub(crate) wav: Vec<u8>,
+}
+
+fn samples_to_ms(samples: usize, pcm: &WavPcm) -> u64 {
+    let frames = samples as u64 / u64::from(pcm.channels.max(1));
+    frames * 1000 / u64::from(pcm.sample_rate.max(1))
+}
+
+/// Where to end a chunk within `samples`: the middle of the quietest
+/// [`PAUSE_MS`] stretch in the last `search_window_ms`, judged by its loudest
+/// frame so a short gap inside a word does not count as a pause. Only the
+/// second half is searched and later stretches win ties, keeping chunks large.
+fn cut_point(samples: &[i16], pcm: &WavPcm, search_window_ms: u32) -> usize {
+    let channels = usize::from(pcm.channels.max(1));
+    let frame_len = (pcm.sample_rate as usize * FRAME_MS / 1000).max(1) * channels;
+    let window_len = (pcm.sample_rate as usize * search_window_ms as usize / 1000) * channels;
+    let search_start = samples
+        .len()
+        .saturating_sub(window_len)
+        .max(samples.len() / 2)
+        / frame_len
+        * frame_len;
+
+    let levels: Vec<f32> = samples[search_start..]
+        .chunks_exact(frame_len)
+        .map(vad::frame_dbfs)
+        .collect();
+    let pause_frames = (PAUSE_MS / FRAME_MS).min(levels.len()).max(1);
+    if levels.len() < pause_frames {
+        return samples.len() / channels * channels;
+    }
+
+    let mut best = (f32::INFINITY, 0);
+    for first in 0..=levels.len() - pause_frames {
+        let loudest = levels[first..first + pause_frames]
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+        if loudest <= best.0 {
+            best = (loudest, first);
+        }
+    }
+    search_start + (best.1 * 2 + pause_frames) * frame_len / 2 / channels * channels
+}
+
+/// Splits `pcm` into WAV chunks of at most `max_chunk_bytes`, ending each one
+/// at a pause where it can.
+pub(crate) fn split_on_silence(pcm: &WavPcm, settings: &ChunkingSettings) -> Vec<AudioChunk> {
+    let channels = usize::from(pcm.channels.max(1));
+    // WAV headers hold sizes in 32 bits, whatever the setting says.
+    let max_bytes = settings.max_chunk_bytes.min(u32::MAX as usize);
+    let max_samples = (max_bytes.saturating_sub(WAV_HEADER_BYTES) / 2) / channels * channels;
+    let max_samples = max_samples.max(channels);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < pcm.samples.len() {
+        let remaining = &pcm.samples[start..];
+        let len = if remaining.len() <= max_samples {
+            remaining.len()
+        } else {
+            cut_point(&remaining[..max_samples], pcm, settings.search_window_ms).max(channels)
+        };
+        chunks.push(AudioChunk {
+            start_ms: samples_to_ms(start, pcm),
+            duration_ms: samples_to_ms(len, pcm),
+            wav: wav::write_pcm16(&remaining[..len], pcm.channels, pcm.sample_rate),
+        });
+        start += len;
+    }
+    chunks
+}
+
+/// Joins chunk transcripts in recording order. Segment times are moved from
+/// chunk time to recording time; a chunk without segments becomes one
+/// segment covering the whole chunk.
+pub(crate) fn stitch(
+    chunks: &[AudioChunk],
+    responses: Vec<TranscriptionResponse>,
+) -> TranscriptionResponse {
+    let mut stitched = TranscriptionResponse::default();
+    let mut texts = Vec::new();
+    for (chunk, response) in chunks.iter().zip(responses) {
+        let text = response.text.trim();
+        if stitched.language.is_none() {
+            stitched.language = response.language;
+        }
+        if response.segments.is_empty() {
+            if !text.is_empty() {
+                stitched.segments.push(TranscriptSegment {
+                    start_ms: chunk.start_ms,
+                    end_ms: chunk.start_ms + chunk.duration_ms,
+                    text: text.to_string(),
+                });
+            }
+        } else {
+            for segment in response.segments {
+                stitched.segments.push(TranscriptSegment {
+                    start_ms: chunk.start_ms + segment.start_ms,
+                    end_ms: chunk.start_ms + segment.end_ms,
+                    text: segment.text,
+                });
+            }
+        }
+        if !text.is_empty() {
+            texts.push(text.to_string());
+        }
+    }
+    stitched.text = texts.join(" ");
+    stitched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    /// Mono tone with silent gaps at the given times, in ms.
+    fn speech_with_pauses(total_ms: usize, pauses: &[(usize, usize)]) -> WavPcm {
+        let samples = (0..total_ms * RATE as usize / 1000)
+            .map(|i| {
+                let ms = i * 1000 / RATE as usize;
+                if pauses.iter().any(|&(from, to)| (from..to).contains(&ms)) {
+                    0
+                } else {
+                    let t = i as f32 / RATE as f32;
+                    (8_000.0 * (2.0 * std::f32::consts::PI * 220.0 * t).sin()) as i16
+                }
+            })
+            .collect();
+        WavPcm {
+            sample_rate: RATE,
+            channels: 1,
+            samples,
+        }
+    }
+
+    #[test]
+    fn small_recordings_stay_whole() {
+        let pcm = speech_with_pauses(2_000, &[]);
+        let chunks = split_on_silence(&pcm, &ChunkingSettings::default());
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].wav, wav::write_pcm16(&pcm.samples, 1, RATE));
+    }
+
+    #[test]
+    fn chunks_end_in_pauses_and_fit_the_limit() {
+        // Ten seconds with pauses at 3.0-3.5 s and 6.5-7.0 s; chunks may hold
+        // at most four seconds.
+        let pcm = speech_with_pauses(10_000, &[(3_000, 3_500), (6_500, 7_000)]);
+        let settings = ChunkingSettings {
+            max_chunk_bytes: WAV_HEADER_BYTES + 4 * RATE as usize * 2,
+            search_window_ms: 2_000,
+            ..ChunkingSettings::default()
+        };
+        let chunks = split_on_silence(&pcm, &settings);
+
+        let starts: Vec<u64> = chunks.iter().map(|chunk| chunk.start_ms).collect();
+        assert_eq!(starts.len(), 3);
+        assert!((3_000..3_500).contains(&starts[1]), "{:?}", starts);
+        assert!((6_500..7_000).contains(&starts[2]), "{:?}", starts);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.wav.len() <= settings.max_chunk_bytes));
+
+        let rejoined: Vec<i16> = chunks
+            .iter()
+            .flat_map(|chunk| wav::read_pcm16(&chunk.wav).unwrap().samples)
+            .collect();
+        assert_eq!(rejoined, pcm.samples);
+        let total_ms: u64 = chunks.iter().map(|chunk| chunk.duration_ms).sum();
+        assert_eq!(total_ms, 10_000);
+    }
+
+    #[test]
+    fn recordings_without_pauses_are_cut_at_the_limit() {
+        let pcm = WavPcm {
+            sample_rate: RATE,
+            channels: 2,
+            samples: speech_with_pauses(5_000, &[])
+                .samples
+                .into_iter()
+                .flat_map(|sample| [sample, sample])
+                .collect(),
+        };
+        let settings = ChunkingSettings {
+            max_chunk_bytes: WAV_HEADER_BYTES + 2 * RATE as usize * 2 * 2,
+            ..ChunkingSettings::default()
+        };
+        let chunks = split_on_silence(&pcm, &settings);
+        assert!(chunks.len() >= 3);
+        for chunk in &chunks {
+            let decoded = wav::read_pcm16(&chunk.wav).unwrap();
+            assert_eq!(decoded.channels, 2);
+            assert_eq!(decoded.samples.len() % 2, 0);
+            assert!(chunk.wav.len() <= settings.max_chunk_bytes);
+        }
+    }
+
+    #[test]
+    fn stitched_segments_use_recording_time() {
+        let chunk = |start_ms, duration_ms| AudioChunk {
+            start_ms,
+            duration_ms,
+            wav: Vec::new(),
+        };
+        let chunks = [chunk(0, 4_000), chunk(4_000, 3_000), chunk(7_000, 2_000)];
+        let responses = vec![
+            TranscriptionResponse {
+                text: " Hello there. ".to_string(),
+                language: Some("en".to_string()),
+                segments: vec![TranscriptSegment {
+                    start_ms: 500,
+                    end_ms: 3_500,
+                    text: "Hello there.".to_string(),
+                }],
+                ..TranscriptionResponse::default()
+            },
+            TranscriptionResponse::default(),
+            TranscriptionResponse {
+                text: "General Kenobi.".to_string(),
+                ..TranscriptionResponse::default()
+            },
+        ];
+
+        let stitched = stitch(&chunks, responses);
+        assert_eq!(stitched.text, "Hello there. General Kenobi.");
+        assert_eq!(stitched.language.as_deref(), Some("en"));
+        let times: Vec<(u64, u64)> = stitched
+            .segments
+            .iter()
+            .map(|segment| (segment.start_ms, segment.end_ms))
+            .collect();
+        assert_eq!(times, vec![(500, 3_500), (7_000, 9_000)]);
+    }
+}
@@ -0,0 +1,357 @@
+use rodio::Source;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long a capture gate waits for the cue to report that it started
+/// before letting audio through anyway.
+const MAX_CUE_WAIT: Duration = Duration::from_secs(2);
+
+/// How the record-start cue is kept out of the captured audio.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StartCueMode {
+    /// Discard captured audio until the cue has finished playing. A
+    /// warm-microphone pre-roll is still prepended, with the cue silenced.
+    DelayCapture,
+    /// Capture immediately and silence the cue's time span afterwards.
+    #[default]
+    Trim,
+    /// Leave the cue in the recording.
+    Off,
+}
+
+/// Stored under [`crate::settings::START_CUE_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct StartCueSettings {
+    pub(crate) mode: StartCueMode,
+    /// Extra time treated as cue after playback ends, covering output latency
+    /// and room echo.
+    pub(crate) margin_ms: u64,
+}
+
+impl Default for StartCueSettings {
+    fn default() -> Self {
+        Self {
+            mode: StartCueMode::Trim,
+            margin_ms: 80,
+        }
+    }
+}
+
+impl StartCueSettings {
+    fn margin(&self) -> Duration {
+        Duration::from_millis(self.margin_ms)
+    }
+
+    /// The capture gate for a session, when capture should wait for the cue.
+    pub(crate) fn gate(&self, timing: &CueTiming) -> Option<CueGate> {
+        (self.mode == StartCueMode::DelayCapture).then(|| CueGate {
+            timing: timing.clone(),
+            margin: self.margin(),
+            created_at: Instant::now(),
+        })
+    }
+
+    /// Silences the cue in mono `samples` that were captured from
+    /// `captured_from` onwards. Returns the number of samples muted, which is
+    /// always zero outside [`StartCueMode::Trim`].
+    pub(crate) fn trim(
+        &self,
+        timing: &CueTiming,
+        captured_from: Option<Instant>,
+        samples: &mut [i16],
+        sample_rate: u32,
+    ) -> usize {
+        if self.mode != StartCueMode::Trim {
+            return 0;
+        }
+        let (Some(span), Some(captured_from)) = (timing.span(), captured_from) else {
+            return 0;
+        };
+        let range = cue_sample_range(
+            captured_from,
+            span,
+            self.margin(),
+            sample_rate,
+            samples.len(),
+        );
+        samples[range.clone()].fill(0);
+        range.len()
+    }
+}
+
+/// When a cue started playing and for how long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct CueSpan {
+    pub(crate) started_at: Instant,
+    pub(crate) duration: Duration,
+}
+
+impl CueSpan {
+    fn end(&self) -> Instant {
+        self.started_at + self.duration
+    }
+}
+
+/// Filled in by the playback thread once the cue is handed to the output
+/// device. A cue that fails to play is recorded with a zero duration.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CueTiming(Arc<OnceLock<CueSpan>>);
+
+impl CueTiming {
+    /// Records that the cue starts now; later calls are ignored.
+    pub(crate) fn mark(&self, duration: Duration) {
+        self.mark_at(Instant::now(), duration);
+    }
+
+    fn mark_at(&self, started_at: Instant, duration: Duration) {
+        let _ = self.0.set(CueSpan {
+            started_at,
+            duration,
+        });
+    }
+
+    pub(crate) fn span(&self) -> Option<CueSpan> {
+        self.0.get().copied()
+    }
+}
+
+/// Holds capture closed until the cue and its margin are over.
+pub(crate) struct CueGate {
+    timing: CueTiming,
+    margin: Duration,
+    created_at: Instant,
+}
+
+impl CueGate {
+    pub(crate) fn is_open(&self, now: Instant) -> bool {
+        match self.timing.span() {
+            Some(span) => now >= span.end() + self.margin,
+            // Playback never reported in; don't hold the session hostage.
+            None => now.saturating_duration_since(self.created_at) >= MAX_CUE_WAIT,
+        }
+    }
+
+    /// Interleaved sample indices covered by the cue in `len` samples of
+    /// audio that ended at `ends_at`, such as a warm-microphone pre-roll.
+    pub(crate) fn cue_range(
+        &self,
+        ends_at: Instant,
+        sample_rate: u32,
+        channels: u16,
+        len: usize,
+    ) -> Range<usize> {
+        let Some(span) = self.timing.span() else {
+            return 0..0;
+        };
+        let channels = usize::from(channels.max(1));
+        let frames = len / channels;
+        let length = Duration::from_secs_f64(frames as f64 / f64::from(sample_rate.max(1)));
+        let captured_from = ends_at.checked_sub(length).unwrap_or(ends_at);
+        let range = cue_sample_range(captured_from, span, self.margin, sample_rate, frames);
+        range.start * channels..range.end * channels
+    }
+}
+
+/// Mono sample indices covered by the cue, for a capture whose first sample
+/// was recorded at `captured_from`.
+fn cue_sample_range(
+    captured_from: Instant,
+    span: CueSpan,
+    margin: Duration,
+    sample_rate: u32,
+    len: usize,
+) -> Range<usize> {
+    let to_index = |at: Instant| {
+        let offset = at.saturating_duration_since(captured_from);
+        ((offset.as_secs_f64() * f64::from(sample_rate)) as usize).min(len)
+    };
+    to_index(span.started_at)..to_index(span.end() + margin)
+}
+
+/// Length of the sound file at `path`, decoding it when the container does
+/// not state a duration.
+pub(crate) fn sound_duration(path: &Path) -> Option<Duration> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let decoder = rodio::Decoder::new(file).ok()?;
+    if let Some(duration) = decoder.total_duration() {
+        return Some(duration);
+    }
+
+    let channels = u64::from(decoder.channels().max(1));
+    let sample_rate = u64::from(decoder.sample_rate().max(1));
+    let frames = decoder.count() as u64 / channels;
+    Some(Duration::from_micros(frames * 1_000_000 / sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn tone(frequency: f64, amplitude: f64, seconds: f64) -> Vec<f64> {
+        let len = (seconds * f64::from(SAMPLE_RATE)) as usize;
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / f64::from(SAMPLE_RATE);
+                amplitude * (2.0 * std::f64::consts::PI * frequency * t).sin()
+            })
+            .collect()
+    }
+
+    fn to_pcm16(wave: &[f64]) -> Vec<i16> {
+        wave.iter()
+            .map(|&s| (s.clamp(-1.0, 1.0) * f64::from(i16::MAX)).round() as i16)
+            .collect()
+    }
+
+    fn index(seconds: f64) -> usize {
+        (seconds * f64::from(SAMPLE_RATE)) as usize
+    }
+
+    /// Two seconds of "voice" with a 300 ms chime mixed in 100 ms after
+    /// capture started.
+    fn voice_with_chime() -> (Vec<i16>, Vec<i16>) {
+        let voice = tone(220.0, 0.3, 2.0);
+        let chime = tone(1760.0, 0.5, 0.3);
+        let mut mixed = voice.clone();
+        for (i, sample) in chime.iter().enumerate() {
+            mixed[index(0.1) + i] += sample;
+        }
+        (to_pcm16(&mixed), to_pcm16(&voice))
+    }
+
+    fn settings(mode: StartCueMode) -> StartCueSettings {
+        StartCueSettings {
+            mode,
+            margin_ms: 50,
+        }
+    }
+
+    #[test]
+    fn trim_silences_cue_and_keeps_the_rest() {
+        let (mut captured, voice) = voice_with_chime();
+        let captured_from = Instant::now();
+        let timing = CueTiming::default();
+        timing.mark_at(
+            captured_from + Duration::from_millis(100),
+            Duration::from_millis(300),
+        );
+
+        let muted = settings(StartCueMode::Trim).trim(
+            &timing,
+            Some(captured_from),
+            &mut captured,
+            SAMPLE_RATE,
+        );
+
+        let cue_end = index(0.45);
+        assert_eq!(muted, cue_end - index(0.1));
+        assert!(captured[..index(0.1)] == voice[..index(0.1)]);
+        assert!(captured[index(0.1)..cue_end].iter().all(|&s| s == 0));
+        assert!(captured[cue_end..] == voice[cue_end..]);
+    }
+
+    #[test]
+    fn trim_handles_cue_before_capture_started() {
+        let (mut captured, voice) = voice_with_chime();
+        let captured_from = Instant::now() + Duration::from_millis(200);
+        let timing = CueTiming::default();
+        timing.mark_at(
+            captured_from - Duration::from_millis(200),
+            Duration::from_millis(300),
+        );
+
+        let muted = settings(StartCueMode::Trim).trim(
+            &timing,
+            Some(captured_from),
+            &mut captured,
+            SAMPLE_RATE,
+        );
+
+        assert_eq!(muted, index(0.15));
+        assert!(captured[..index(0.15)].iter().all(|&s| s == 0));
+        assert!(captured[index(0.45)..] == voice[index(0.45)..]);
+    }
+
+    #[test]
+    fn other_modes_leave_audio_untouched() {
+        let (captured, _) = voice_with_chime();
+        let captured_from = Instant::now();
+        let timing = CueTiming::default();
+        timing.mark_at(captured_from, Duration::from_millis(300));
+
+        for mode in [StartCueMode::DelayCapture, StartCueMode::Off] {
+            let mut samples = captured.clone();
+            let muted =
+                settings(mode).trim(&timing, Some(captured_from), &mut samples, SAMPLE_RATE);
+            assert_eq!(muted, 0);
+            assert_eq!(samples, captured);
+        }
+    }
+
+    #[test]
+    fn delayed_capture_drops_cue_buffers() {
+        let (captured, voice) = voice_with_chime();
+        let timing = CueTiming::default();
+        let gate = settings(StartCueMode::DelayCapture).gate(&timing).unwrap();
+        let captured_from = gate.created_at;
+        timing.mark_at(
+            captured_from + Duration::from_millis(100),
+            Duration::from_millis(300),
+        );
+
+        // Feed 10 ms callback buffers through the gate as the capture loop would.
+        let buffer_len = index(0.01);
+        let mut recorded = Vec::new();
+        for (n, buffer) in captured.chunks(buffer_len).enumerate() {
+            let arrived_at = captured_from + Duration::from_millis(10 * (n as u64 + 1));
+            if gate.is_open(arrived_at) {
+                recorded.extend_from_slice(buffer);
+            }
+        }
+
+        let first_kept = index(0.44);
+        assert_eq!(recorded.len(), captured.len() - first_kept);
+        assert!(recorded == voice[first_kept..]);
+    }
+
+    #[test]
+    fn gate_locates_cue_in_preroll() {
+        let timing = CueTiming::default();
+        let gate = settings(StartCueMode::DelayCapture).gate(&timing).unwrap();
+        let opened_at = gate.created_at + Duration::from_secs(1);
+        // A 300 ms cue ending 250 ms before the gate opened, plus 50 ms margin.
+        timing.mark_at(
+            opened_at - Duration::from_millis(550),
+            Duration::from_millis(300),
+        );
+
+        // One second of stereo pre-roll ending when the gate opened.
+        let len = 2 * index(1.0);
+        let range = gate.cue_range(opened_at, SAMPLE_RATE, 2, len);
+        assert_eq!(range, 2 * index(0.45)..2 * index(0.8));
+    }
+
+    #[test]
+    fn gate_opens_when_cue_never_reports() {
+        let gate = settings(StartCueMode::DelayCapture)
+            .gate(&CueTiming::default())
+            .unwrap();
+        assert!(!gate.is_open(gate.created_at));
+        assert!(gate.is_open(gate.created_at + MAX_CUE_WAIT));
+    }
+
+    #[test]
+    fn only_delay_mode_gates_capture() {
+        let timing = CueTiming::default();
+        assert!(settings(StartCueMode::Trim).gate(&timing).is_none());
+        assert!(settings(StartCueMode::Off).gate(&timing).is_none());
+    }
+}
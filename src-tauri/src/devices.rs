@@ -0,0 +1,273 @@
+use crate::settings;
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::collections::HashMap;
+use tauri::Emitter;
+
+const EVENT_AUDIO_DEVICE_FALLBACK: &str = "audio_device_fallback";
+/// Software input gain is limited to this many dB either way.
+pub(crate) const MAX_INPUT_GAIN_DB: f32 = 30.0;
+
+#[derive(Clone, serde::Serialize)]
+struct DeviceFallback {
+    requested: String,
+    fallback: Option<String>,
+    reason: String,
+}
+
+pub(crate) struct InputDevice {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) device: cpal::Device,
+}
+
+/// Builds an identifier that survives re-enumeration: the host, the device
+/// name and the position among devices sharing that name.
+fn stable_device_id(host_id: cpal::HostId, name: &str, duplicate_index: usize) -> String {
+    format!("{}:{}#{}", host_id.name(), name, duplicate_index)
+}
+
+pub(crate) fn list_input_devices(host: &cpal::Host) -> Result<Vec<InputDevice>, String> {
+    let devices = host
+        .input_devices()
+        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?;
+
+    let mut input_devices: Vec<InputDevice> = Vec::new();
+    for device in devices {
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let duplicate_index = input_devices.iter().filter(|d| d.name == name).count();
+        input_devices.push(InputDevice {
+            id: stable_device_id(host.id(), &name, duplicate_index),
+            name,
+            device,
+        });
+    }
+
+    Ok(input_devices)
+}
+
+/// The identity of a connected input device, without the cpal handle.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub(crate) struct DeviceInfo {
+    pub(crate) id: String,
+    pub(crate) name: String,
+}
+
+impl InputDevice {
+    pub(crate) fn info(&self) -> DeviceInfo {
+        DeviceInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+/// Finds a device by stable ID, or by name for selections saved before IDs
+/// were stable.
+fn position(devices: &[DeviceInfo], id_or_name: &str) -> Option<usize> {
+    let by_id = devices.iter().position(|d| d.id == id_or_name);
+    by_id.or_else(|| devices.iter().position(|d| d.name == id_or_name))
+}
+
+/// Looks a device up by stable ID, or by name for selections saved before
+/// IDs were stable.
+pub(crate) fn find_input_device(host: &cpal::Host, id_or_name: &str) -> Option<InputDevice> {
+    let devices = list_input_devices(host).ok()?;
+    let infos: Vec<DeviceInfo> = devices.iter().map(InputDevice::info).collect();
+    let index = position(&infos, id_or_name)?;
+    devices.into_iter().nth(index)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeviceSource {
+    Selected,
+    Preferred,
+    HostDefault,
+}
+
+/// Picks the capture device among `devices`: the selection if it is
+/// connected, then the first connected entry of `priority`, otherwise the
+/// host default. Returns the index into `devices` and where it came from.
+pub(crate) fn choose_input(
+    devices: &[DeviceInfo],
+    selected: Option<&str>,
+    priority: &[String],
+    default_name: Option<&str>,
+) -> Option<(usize, DeviceSource)> {
+    if let Some(index) = selected.and_then(|id| position(devices, id)) {
+        return Some((index, DeviceSource::Selected));
+    }
+    if let Some(index) = priority.iter().find_map(|id| position(devices, id)) {
+        return Some((index, DeviceSource::Preferred));
+    }
+    let index = default_name.and_then(|name| devices.iter().position(|d| d.name == name))?;
+    Some((index, DeviceSource::HostDefault))
+}
+
+/// The saved selection, priority list and host default name that
+/// [`choose_input`] works from.
+pub(crate) fn selection(
+    app_handle: &tauri::AppHandle,
+    host: &cpal::Host,
+) -> (Option<String>, Vec<String>, Option<String>) {
+    let selected: Option<String> = settings::read(app_handle, settings::SELECTED_AUDIO_DEVICE_KEY);
+    let priority: Vec<String> =
+        settings::read(app_handle, settings::AUDIO_DEVICE_PRIORITY_KEY).unwrap_or_default();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    (selected.filter(|id| !id.is_empty()), priority, default_name)
+}
+
+/// Tells the frontend that capture moved off the selected device.
+pub(crate) fn report_fallback(
+    app_handle: &tauri::AppHandle,
+    requested: &str,
+    fallback: Option<String>,
+    reason: String,
+) {
+    eprintln!(
+        "Selected input device '{}' not available, falling back to {:?}",
+        requested, fallback
+    );
+    let payload = DeviceFallback {
+        requested: requested.to_string(),
+        fallback,
+        reason,
+    };
+    if let Err(e) = app_handle.emit(EVENT_AUDIO_DEVICE_FALLBACK, &payload) {
+        eprintln!(
+            "Failed to emit {} event: {}",
+            EVENT_AUDIO_DEVICE_FALLBACK, e
+        );
+    }
+}
+
+/// Software input gain saved for `device_id`, in dB.
+pub(crate) fn input_gain_db(app_handle: &tauri::AppHandle, device_id: &str) -> f32 {
+    let gains: HashMap<String, f32> =
+        settings::read(app_handle, settings::AUDIO_DEVICE_GAIN_KEY).unwrap_or_default();
+    gains.get(device_id).copied().unwrap_or(0.0)
+}
+
+pub(crate) fn set_input_gain_db(
+    app_handle: &tauri::AppHandle,
+    device_id: &str,
+    gain_db: f32,
+) -> Result<f32, String> {
+    if !gain_db.is_finite() {
+        return Err(format!("Invalid input gain: {}", gain_db));
+    }
+    let gain_db = gain_db.clamp(-MAX_INPUT_GAIN_DB, MAX_INPUT_GAIN_DB);
+    let mut gains: HashMap<String, f32> =
+        settings::read(app_handle, settings::AUDIO_DEVICE_GAIN_KEY).unwrap_or_default();
+    if gain_db == 0.0 {
+        gains.remove(device_id);
+    } else {
+        gains.insert(device_id.to_string(), gain_db);
+    }
+    settings::write(app_handle, settings::AUDIO_DEVICE_GAIN_KEY, &gains)?;
+    Ok(gain_db)
+}
+
+/// Returns the capture device for a new session: the saved selection if it is
+/// still connected, then the first connected entry of the priority list,
+/// otherwise the host default.
+pub(crate) fn resolve_input_device(app_handle: &tauri::AppHandle) -> Result<InputDevice, String> {
+    let host = cpal::default_host();
+    let (selected, priority, default_name) = selection(app_handle, &host);
+    let devices = list_input_devices(&host)?;
+    let infos: Vec<DeviceInfo> = devices.iter().map(InputDevice::info).collect();
+
+    let choice = choose_input(
+        &infos,
+        selected.as_deref(),
+        &priority,
+        default_name.as_deref(),
+    );
+    let source = choice.map(|(_, source)| source);
+    let input = choice.and_then(|(index, _)| devices.into_iter().nth(index));
+
+    match (&input, source) {
+        (Some(input), Some(DeviceSource::Selected)) => {
+            println!("Using selected input device: {} ({})", input.name, input.id);
+        }
+        (Some(input), Some(DeviceSource::Preferred)) => {
+            println!(
+                "Using preferred input device: {} ({})",
+                input.name, input.id
+            );
+        }
+        _ => {}
+    }
+
+    if let Some(requested) = selected.as_deref() {
+        if source != Some(DeviceSource::Selected) {
+            report_fallback(
+                app_handle,
+                requested,
+                input.as_ref().map(|input| input.name.clone()),
+                format!("Selected device '{}' is not connected", requested),
+            );
+        }
+    }
+
+    input.ok_or_else(|| "No input device available".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: format!("host:{}#0", name),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn connected_selection_wins() {
+        let devices = vec![device("Built-in"), device("Headset")];
+        let priority = vec!["host:Built-in#0".to_string()];
+        assert_eq!(
+            choose_input(
+                &devices,
+                Some("host:Headset#0"),
+                &priority,
+                Some("Built-in")
+            ),
+            Some((1, DeviceSource::Selected))
+        );
+        // Selections saved by name still resolve.
+        assert_eq!(
+            choose_input(&devices, Some("Headset"), &[], None),
+            Some((1, DeviceSource::Selected))
+        );
+    }
+
+    #[test]
+    fn missing_selection_falls_back_to_priority_then_default() {
+        let devices = vec![device("Built-in"), device("USB Interface")];
+        let priority = vec![
+            "host:Headset#0".to_string(),
+            "host:USB Interface#0".to_string(),
+        ];
+        assert_eq!(
+            choose_input(
+                &devices,
+                Some("host:Headset#0"),
+                &priority,
+                Some("Built-in")
+            ),
+            Some((1, DeviceSource::Preferred))
+        );
+        assert_eq!(
+            choose_input(&devices, Some("host:Headset#0"), &[], Some("Built-in")),
+            Some((0, DeviceSource::HostDefault))
+        );
+        assert_eq!(
+            choose_input(&[], Some("host:Headset#0"), &priority, None),
+            None
+        );
+    }
+}
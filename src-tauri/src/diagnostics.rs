@@ -0,0 +1,191 @@
+//! Quality checks on the raw capture, run before any gain or filtering so
+//! clipping and DC offset are measured as the device delivered them. The
+//! level is judged with the device's input gain applied, since that is what
+//! reaches speech-to-text.
+
+/// Samples at or above this magnitude count as clipped.
+const CLIP_LEVEL: u16 = i16::MAX as u16 - 64;
+/// Clipping above this share of samples is reported.
+const CLIPPING_RATIO: f32 = 0.001;
+/// Clipping above this share of samples makes a recording unusable.
+const HEAVY_CLIPPING_RATIO: f32 = 0.05;
+/// Recordings below this RMS level are treated as a dead or muted microphone.
+const TOO_QUIET_DBFS: f32 = -60.0;
+/// A mean offset above this fraction of full scale is reported.
+const DC_OFFSET_LIMIT: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QualityIssue {
+    /// Every sample is exactly zero: the device is muted or access is blocked.
+    DigitalSilence,
+    TooQuiet,
+    Clipping,
+    DcOffset,
+}
+
+/// Measurements for one finished recording, attached to session events.
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct RecordingDiagnostics {
+    pub(crate) duration_ms: u64,
+    /// RMS level after the device's input gain.
+    pub(crate) rms_dbfs: f32,
+    pub(crate) peak_dbfs: f32,
+    pub(crate) clipped_ratio: f32,
+    /// Mean sample value as a fraction of full scale.
+    pub(crate) dc_offset: f32,
+    pub(crate) issues: Vec<QualityIssue>,
+    /// Whether the recording is worth sending to speech-to-text.
+    pub(crate) usable: bool,
+    /// User-facing explanation when the recording is not usable.
+    pub(crate) warning: Option<String>,
+}
+
+fn to_dbfs(level: f64) -> f32 {
+    (20.0 * level.max(1e-9).log10()) as f32
+}
+
+/// Analyzes interleaved 16-bit `pcm` at the device's native format, before
+/// `gain_db` of per-device input gain is applied.
+pub(crate) fn analyze(
+    pcm: &[i16],
+    sample_rate: u32,
+    channels: u16,
+    gain_db: f32,
+) -> RecordingDiagnostics {
+    let frames = pcm.len() as u64 / u64::from(channels.max(1));
+    let duration_ms = frames * 1000 / u64::from(sample_rate.max(1));
+
+    let mut sum = 0i64;
+    let mut sum_squares = 0f64;
+    let mut peak = 0u16;
+    let mut clipped = 0usize;
+    for &sample in pcm {
+        let magnitude = sample.unsigned_abs();
+        sum += i64::from(sample);
+        sum_squares += f64::from(sample) * f64::from(sample);
+        peak = peak.max(magnitude);
+        if magnitude >= CLIP_LEVEL {
+            clipped += 1;
+        }
+    }
+
+    let count = pcm.len().max(1) as f64;
+    let full_scale = f64::from(i16::MAX);
+    let rms_dbfs = to_dbfs((sum_squares / count).sqrt() / full_scale) + gain_db;
+    let clipped_ratio = (clipped as f64 / count) as f32;
+    let dc_offset = (sum as f64 / count / full_scale) as f32;
+
+    let mut issues = Vec::new();
+    let mut warning = None;
+    if !pcm.is_empty() {
+        if peak == 0 {
+            issues.push(QualityIssue::DigitalSilence);
+            warning = Some(
+                "The microphone recorded pure silence. It may be muted, or microphone access may be blocked."
+                    .to_string(),
+            );
+        } else if rms_dbfs < TOO_QUIET_DBFS {
+            issues.push(QualityIssue::TooQuiet);
+            warning = Some(
+                "The recording is too quiet to transcribe. Check the microphone and its input gain."
+                    .to_string(),
+            );
+        }
+        if clipped_ratio > CLIPPING_RATIO {
+            issues.push(QualityIssue::Clipping);
+            if clipped_ratio > HEAVY_CLIPPING_RATIO && warning.is_none() {
+                warning = Some(
+                    "The recording is heavily clipped. Lower the microphone's input level."
+                        .to_string(),
+                );
+            }
+        }
+        if dc_offset.abs() > DC_OFFSET_LIMIT {
+            issues.push(QualityIssue::DcOffset);
+        }
+    }
+
+    RecordingDiagnostics {
+        duration_ms,
+        rms_dbfs,
+        peak_dbfs: to_dbfs(f64::from(peak) / full_scale),
+        clipped_ratio,
+        dc_offset,
+        issues,
+        usable: warning.is_none(),
+        warning,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn tone(amplitude: f64, offset: f64) -> Vec<i16> {
+        (0..SAMPLE_RATE as usize)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * 200.0 * i as f64 / f64::from(SAMPLE_RATE);
+                ((offset + amplitude * phase.sin()).clamp(-1.0, 1.0) * f64::from(i16::MAX)).round()
+                    as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn normal_speech_level_is_usable() {
+        let diagnostics = analyze(&tone(0.3, 0.0), SAMPLE_RATE, 1, 0.0);
+        assert!(diagnostics.usable);
+        assert!(diagnostics.issues.is_empty());
+        assert_eq!(diagnostics.duration_ms, 1000);
+        assert!((diagnostics.rms_dbfs + 13.5).abs() < 0.5);
+    }
+
+    #[test]
+    fn all_zero_capture_is_digital_silence() {
+        let diagnostics = analyze(&vec![0; 32_000], SAMPLE_RATE, 2, 0.0);
+        assert_eq!(diagnostics.issues, vec![QualityIssue::DigitalSilence]);
+        assert!(!diagnostics.usable);
+        assert_eq!(diagnostics.duration_ms, 1000);
+    }
+
+    #[test]
+    fn near_silent_capture_is_too_quiet() {
+        let diagnostics = analyze(&tone(0.0005, 0.0), SAMPLE_RATE, 1, 0.0);
+        assert_eq!(diagnostics.issues, vec![QualityIssue::TooQuiet]);
+        assert!(!diagnostics.usable);
+    }
+
+    #[test]
+    fn input_gain_counts_towards_the_level() {
+        let diagnostics = analyze(&tone(0.0005, 0.0), SAMPLE_RATE, 1, 20.0);
+        assert!(diagnostics.issues.is_empty());
+        assert!(diagnostics.usable);
+        assert!((diagnostics.rms_dbfs + 49.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn overdriven_capture_is_heavily_clipped() {
+        let diagnostics = analyze(&tone(3.0, 0.0), SAMPLE_RATE, 1, 0.0);
+        assert!(diagnostics.clipped_ratio > HEAVY_CLIPPING_RATIO);
+        assert_eq!(diagnostics.issues, vec![QualityIssue::Clipping]);
+        assert!(!diagnostics.usable);
+    }
+
+    #[test]
+    fn dc_offset_is_reported_but_usable() {
+        let diagnostics = analyze(&tone(0.2, 0.1), SAMPLE_RATE, 1, 0.0);
+        assert!((diagnostics.dc_offset - 0.1).abs() < 0.01);
+        assert_eq!(diagnostics.issues, vec![QualityIssue::DcOffset]);
+        assert!(diagnostics.usable);
+    }
+
+    #[test]
+    fn empty_capture_has_no_issues() {
+        let diagnostics = analyze(&[], SAMPLE_RATE, 1, 0.0);
+        assert!(diagnostics.issues.is_empty());
+        assert!(diagnostics.usable);
+    }
+}
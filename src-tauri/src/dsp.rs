@@ -0,0 +1,467 @@
+//! CPU-only clean-up applied to the mono speech PCM before it is encoded.
+//!
+//! Stages run in order on `f32` samples in `[-1.0, 1.0]`: DC offset removal,
+//! a high-pass filter for rumble and hum, then spectral noise suppression.
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Stored under [`crate::settings::DSP_SETTINGS_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct DspSettings {
+    pub(crate) dc_removal: bool,
+    pub(crate) high_pass: bool,
+    pub(crate) high_pass_hz: f32,
+    pub(crate) noise_suppression: bool,
+    /// How much of the estimated noise power is subtracted; above 1.0 trades
+    /// speech detail for less residual noise.
+    pub(crate) noise_oversubtraction: f32,
+    /// Most a frequency bin is ever attenuated by the noise suppressor.
+    pub(crate) noise_max_reduction_db: f32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            dc_removal: true,
+            high_pass: true,
+            high_pass_hz: 80.0,
+            noise_suppression: false,
+            noise_oversubtraction: 1.5,
+            noise_max_reduction_db: 20.0,
+        }
+    }
+}
+
+/// One step of the chain. Stages see a whole recording at a time, so they
+/// may look ahead (the noise suppressor estimates its noise profile that way).
+pub(crate) trait DspStage: Send {
+    fn name(&self) -> &'static str;
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+#[derive(Default)]
+pub(crate) struct DspChain {
+    stages: Vec<Box<dyn DspStage>>,
+}
+
+impl DspChain {
+    pub(crate) fn from_settings(settings: &DspSettings, sample_rate: u32) -> Self {
+        let mut chain = Self::default();
+        if settings.dc_removal {
+            chain.push(Box::new(DcBlocker::new(sample_rate)));
+        }
+        if settings.high_pass {
+            chain.push(Box::new(HighPass::new(sample_rate, settings.high_pass_hz)));
+        }
+        if settings.noise_suppression {
+            chain.push(Box::new(SpectralNoiseSuppressor::new(
+                sample_rate,
+                settings.noise_oversubtraction,
+                settings.noise_max_reduction_db,
+            )));
+        }
+        chain
+    }
+
+    pub(crate) fn push(&mut self, stage: Box<dyn DspStage>) {
+        self.stages.push(stage);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub(crate) fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// Runs every stage over `pcm` in place.
+    pub(crate) fn process_pcm16(&mut self, pcm: &mut [i16]) {
+        if self.stages.is_empty() {
+            return;
+        }
+        let scale = f32::from(i16::MAX);
+        let mut samples: Vec<f32> = pcm.iter().map(|&s| f32::from(s) / scale).collect();
+        for stage in &mut self.stages {
+            stage.process(&mut samples);
+        }
+        for (out, sample) in pcm.iter_mut().zip(&samples) {
+            *out = (sample.clamp(-1.0, 1.0) * scale).round() as i16;
+        }
+    }
+}
+
+/// First-order DC blocker: `y[n] = x[n] - x[n-1] + r * y[n-1]`.
+pub(crate) struct DcBlocker {
+    r: f64,
+}
+
+impl DcBlocker {
+    /// Pole placed for a corner around 10 Hz.
+    const CORNER_HZ: f64 = 10.0;
+
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        let r = 1.0 - 2.0 * PI * Self::CORNER_HZ / f64::from(sample_rate.max(1));
+        Self {
+            r: r.clamp(0.9, 0.9999),
+        }
+    }
+}
+
+impl DspStage for DcBlocker {
+    fn name(&self) -> &'static str {
+        "dc_removal"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        let Some(&first) = samples.first() else {
+            return;
+        };
+        // Seed with the first sample so a constant offset does not produce a step.
+        let mut previous_input = f64::from(first);
+        let mut previous_output = 0.0;
+        for sample in samples.iter_mut() {
+            let input = f64::from(*sample);
+            let output = input - previous_input + self.r * previous_output;
+            previous_input = input;
+            previous_output = output;
+            *sample = output as f32;
+        }
+    }
+}
+
+/// Second-order Butterworth high-pass (RBJ cookbook biquad).
+pub(crate) struct HighPass {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl HighPass {
+    pub(crate) fn new(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let nyquist = f64::from(sample_rate.max(2)) / 2.0;
+        let cutoff = f64::from(cutoff_hz).clamp(1.0, nyquist * 0.9);
+        let w0 = 2.0 * PI * cutoff / f64::from(sample_rate.max(2));
+        let alpha = w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b: [
+                (1.0 + cos_w0) / 2.0 / a0,
+                -(1.0 + cos_w0) / a0,
+                (1.0 + cos_w0) / 2.0 / a0,
+            ],
+            a: [-2.0 * cos_w0 / a0, (1.0 - alpha) / a0],
+        }
+    }
+}
+
+impl DspStage for HighPass {
+    fn name(&self) -> &'static str {
+        "high_pass"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        // Transposed direct form II, state kept in f64 for low cutoffs.
+        let (mut z1, mut z2) = (0.0f64, 0.0f64);
+        for sample in samples.iter_mut() {
+            let input = f64::from(*sample);
+            let output = self.b[0] * input + z1;
+            z1 = self.b[1] * input - self.a[0] * output + z2;
+            z2 = self.b[2] * input - self.a[1] * output;
+            *sample = output as f32;
+        }
+    }
+}
+
+/// Spectral subtraction over 50%-overlapped, root-Hann windowed frames. The
+/// noise profile is the average spectrum of the quietest tenth of frames.
+pub(crate) struct SpectralNoiseSuppressor {
+    frame_len: usize,
+    window: Vec<f32>,
+    oversubtraction: f32,
+    min_gain: f32,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+}
+
+impl SpectralNoiseSuppressor {
+    /// Frames of roughly 32 ms, rounded up to a power of two.
+    const FRAME_MS: u32 = 32;
+    const POWER_SMOOTHING: f32 = 0.8;
+
+    pub(crate) fn new(sample_rate: u32, oversubtraction: f32, max_reduction_db: f32) -> Self {
+        let frame_len = (sample_rate as usize * Self::FRAME_MS as usize / 1000)
+            .max(64)
+            .next_power_of_two();
+        let window = (0..frame_len)
+            .map(|n| {
+                let hann = 0.5 - 0.5 * (2.0 * PI * n as f64 / frame_len as f64).cos();
+                hann.sqrt() as f32
+            })
+            .collect();
+        let mut planner = FftPlanner::new();
+
+        Self {
+            frame_len,
+            window,
+            oversubtraction: oversubtraction.max(0.0),
+            min_gain: 10f32.powf(-max_reduction_db.abs() / 20.0),
+            forward: planner.plan_fft_forward(frame_len),
+            inverse: planner.plan_fft_inverse(frame_len),
+        }
+    }
+
+    fn hop(&self) -> usize {
+        self.frame_len / 2
+    }
+
+    fn windowed_spectrum(&self, frame: &[f32], buffer: &mut [Complex<f32>]) {
+        for ((slot, &sample), &weight) in buffer.iter_mut().zip(frame).zip(&self.window) {
+            *slot = Complex::new(sample * weight, 0.0);
+        }
+        self.forward.process(buffer);
+    }
+
+    fn noise_power(&self, padded: &[f32], frame_starts: &[usize]) -> Vec<f32> {
+        let mut energies: Vec<(f32, usize)> = frame_starts
+            .iter()
+            .map(|&start| {
+                let frame = &padded[start..start + self.frame_len];
+                (frame.iter().map(|s| s * s).sum(), start)
+            })
+            .collect();
+        energies.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let quiet_frames = (energies.len() / 10).max(1);
+
+        let mut power = vec![0.0f32; self.frame_len];
+        let mut buffer = vec![Complex::new(0.0, 0.0); self.frame_len];
+        for &(_, start) in &energies[..quiet_frames] {
+            self.windowed_spectrum(&padded[start..start + self.frame_len], &mut buffer);
+            for (bin, value) in power.iter_mut().zip(&buffer) {
+                *bin += value.norm_sqr();
+            }
+        }
+        for bin in &mut power {
+            *bin /= quiet_frames as f32;
+        }
+        power
+    }
+}
+
+impl DspStage for SpectralNoiseSuppressor {
+    fn name(&self) -> &'static str {
+        "noise_suppression"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        if samples.len() < self.frame_len {
+            return;
+        }
+        let hop = self.hop();
+        // Lead with one hop of silence so every real sample is covered by two frames.
+        let mut padded = vec![0.0f32; hop];
+        padded.extend_from_slice(samples);
+        padded.resize(padded.len() + self.frame_len, 0.0);
+        let frame_starts: Vec<usize> = (0..=padded.len() - self.frame_len).step_by(hop).collect();
+
+        let noise_power = self.noise_power(&padded, &frame_starts);
+        let min_gain_squared = self.min_gain * self.min_gain;
+        let scale = 1.0 / self.frame_len as f32;
+        let mut output = vec![0.0f32; padded.len()];
+        let mut buffer = vec![Complex::new(0.0, 0.0); self.frame_len];
+        // Per-bin power averaged over time; gains follow it rather than the raw,
+        // highly variable frame power, which keeps "musical noise" down.
+        let mut smoothed_power = noise_power.clone();
+
+        for &start in &frame_starts {
+            self.windowed_spectrum(&padded[start..start + self.frame_len], &mut buffer);
+            for ((value, &noise), smoothed) in
+                buffer.iter_mut().zip(&noise_power).zip(&mut smoothed_power)
+            {
+                let power = value.norm_sqr();
+                *smoothed =
+                    Self::POWER_SMOOTHING * *smoothed + (1.0 - Self::POWER_SMOOTHING) * power;
+                let gain_squared = (1.0
+                    - self.oversubtraction * noise / smoothed.max(f32::MIN_POSITIVE))
+                .max(min_gain_squared);
+                *value *= gain_squared.sqrt();
+            }
+            self.inverse.process(&mut buffer);
+            for ((out, value), &weight) in output[start..start + self.frame_len]
+                .iter_mut()
+                .zip(&buffer)
+                .zip(&self.window)
+            {
+                *out += value.re * scale * weight;
+            }
+        }
+
+        samples.copy_from_slice(&output[hop..hop + samples.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn tone(frequency: f64, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / f64::from(SAMPLE_RATE);
+                amplitude * (2.0 * PI * frequency * t).sin() as f32
+            })
+            .collect()
+    }
+
+    /// Deterministic white noise in `[-amplitude, amplitude]`.
+    fn noise(amplitude: f32, len: usize) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                amplitude * (state as f32 / u32::MAX as f32 * 2.0 - 1.0)
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+    }
+
+    fn db(ratio: f32) -> f32 {
+        20.0 * ratio.log10()
+    }
+
+    #[test]
+    fn dc_blocker_removes_constant_offset() {
+        let mut samples: Vec<f32> = tone(440.0, 0.3, 16_000).iter().map(|s| s + 0.2).collect();
+        DcBlocker::new(SAMPLE_RATE).process(&mut samples);
+
+        let tail = &samples[8_000..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(mean.abs() < 0.005, "mean {}", mean);
+        assert!((rms(tail) - rms(&tone(440.0, 0.3, 8_000))).abs() < 0.01);
+    }
+
+    #[test]
+    fn high_pass_cuts_hum_and_keeps_voice_band() {
+        let mut hum = tone(20.0, 0.5, 32_000);
+        let mut voice = tone(1_000.0, 0.5, 32_000);
+        HighPass::new(SAMPLE_RATE, 80.0).process(&mut hum);
+        HighPass::new(SAMPLE_RATE, 80.0).process(&mut voice);
+
+        let reference = rms(&tone(1_000.0, 0.5, 16_000));
+        assert!(db(rms(&hum[16_000..]) / reference) < -20.0);
+        assert!(db(rms(&voice[16_000..]) / reference).abs() < 0.5);
+    }
+
+    #[test]
+    fn noise_suppressor_reduces_noise_and_keeps_speech() {
+        let len = 48_000;
+        let background = noise(0.05, len);
+        let voice = tone(500.0, 0.3, 16_000);
+        let mut samples = background.clone();
+        for (i, sample) in voice.iter().enumerate() {
+            samples[16_000 + i] += sample;
+        }
+
+        SpectralNoiseSuppressor::new(SAMPLE_RATE, 1.5, 20.0).process(&mut samples);
+
+        let noise_only = db(rms(&samples[2_000..14_000]) / rms(&background[2_000..14_000]));
+        assert!(noise_only < -9.0, "noise reduced by {} dB", noise_only);
+        let voiced = db(rms(&samples[18_000..30_000]) / rms(&voice[2_000..14_000]));
+        assert!(voiced.abs() < 1.0, "voice level changed by {} dB", voiced);
+    }
+
+    #[test]
+    fn disabled_chain_is_identity() {
+        let settings = DspSettings {
+            dc_removal: false,
+            high_pass: false,
+            noise_suppression: false,
+            ..DspSettings::default()
+        };
+        let mut chain = DspChain::from_settings(&settings, SAMPLE_RATE);
+        assert!(chain.is_empty());
+
+        let original: Vec<i16> = (0..1000).map(|i| (i * 37 % 2000 - 1000) as i16).collect();
+        let mut pcm = original.clone();
+        chain.process_pcm16(&mut pcm);
+        assert_eq!(pcm, original);
+    }
+
+    #[test]
+    fn chain_follows_settings_order() {
+        let settings = DspSettings {
+            noise_suppression: true,
+            ..DspSettings::default()
+        };
+        let chain = DspChain::from_settings(&settings, SAMPLE_RATE);
+        assert_eq!(
+            chain.stage_names(),
+            ["dc_removal", "high_pass", "noise_suppression"]
+        );
+    }
+
+    /// Times every stage on the WAV files in `tests/fixtures/dsp` (or the
+    /// directory in `MURMUR_DSP_FIXTURES`). Run with
+    /// `cargo test --lib dsp::tests::benchmark_fixtures -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn benchmark_fixtures() {
+        let directory = std::env::var("MURMUR_DSP_FIXTURES").unwrap_or_else(|_| {
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/dsp").to_string()
+        });
+        let mut paths: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap_or_else(|e| panic!("Cannot read fixtures in {}: {}", directory, e))
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "No WAV fixtures in {}", directory);
+
+        let settings = DspSettings {
+            noise_suppression: true,
+            ..DspSettings::default()
+        };
+        for path in paths {
+            let bytes = std::fs::read(&path).unwrap();
+            let wav = crate::wav::read_pcm16(&bytes).unwrap();
+            let mono = crate::resample::downmix_to_mono(&wav.samples, wav.channels);
+            let audio_secs = mono.len() as f64 / f64::from(wav.sample_rate);
+            let scale = f32::from(i16::MAX);
+            let mut samples: Vec<f32> = mono.iter().map(|&s| f32::from(s) / scale).collect();
+
+            println!(
+                "{} ({:.2} s, {} Hz, input RMS {:.1} dBFS)",
+                path.display(),
+                audio_secs,
+                wav.sample_rate,
+                db(rms(&samples))
+            );
+            let mut chain = DspChain::from_settings(&settings, wav.sample_rate);
+            for stage in &mut chain.stages {
+                let started = Instant::now();
+                stage.process(&mut samples);
+                let elapsed = started.elapsed();
+                println!(
+                    "  {:<18} {:>8.2} ms  {:>7.0}x realtime  output RMS {:.1} dBFS",
+                    stage.name(),
+                    elapsed.as_secs_f64() * 1000.0,
+                    audio_secs / elapsed.as_secs_f64().max(1e-9),
+                    db(rms(&samples))
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Watches for input devices being connected or removed and keeps the rest of
+//! the app pointed at the right one.
+
+use crate::devices::{self, DeviceInfo, DeviceSource};
+use std::thread;
+use std::time::Duration;
+use tauri::Emitter;
+
+const EVENT_AUDIO_DEVICES_CHANGED: &str = "audio_devices_changed";
+const EVENT_AUDIO_DEVICE_RESTORED: &str = "audio_device_restored";
+/// cpal has no portable device notifications, so the device list is polled.
+/// Enumerating devices is not free and can disturb an open stream on some
+/// drivers, so polls are spaced out and skipped while a session captures.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+struct DevicesChanged {
+    added: Vec<DeviceInfo>,
+    removed: Vec<DeviceInfo>,
+    /// Every connected device after the change.
+    devices: Vec<DeviceInfo>,
+    /// Stable ID of the device the next session will record from.
+    active_device: Option<String>,
+}
+
+/// Devices in `current` but not `previous`, and the other way round.
+fn diff(previous: &[DeviceInfo], current: &[DeviceInfo]) -> (Vec<DeviceInfo>, Vec<DeviceInfo>) {
+    let added = current
+        .iter()
+        .filter(|device| !previous.contains(device))
+        .cloned()
+        .collect();
+    let removed = previous
+        .iter()
+        .filter(|device| !current.contains(device))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+/// The device list and selection as of the last poll.
+#[derive(Default)]
+struct Snapshot {
+    devices: Vec<DeviceInfo>,
+    selected: Option<String>,
+    selected_connected: bool,
+    active_device: Option<String>,
+}
+
+fn take_snapshot(app_handle: &tauri::AppHandle) -> Result<Snapshot, String> {
+    let host = cpal::default_host();
+    let (selected, priority, default_name) = devices::selection(app_handle, &host);
+    let devices: Vec<DeviceInfo> = devices::list_input_devices(&host)?
+        .iter()
+        .map(devices::InputDevice::info)
+        .collect();
+    let choice = devices::choose_input(
+        &devices,
+        selected.as_deref(),
+        &priority,
+        default_name.as_deref(),
+    );
+
+    Ok(Snapshot {
+        selected_connected: matches!(choice, Some((_, DeviceSource::Selected))),
+        active_device: choice.map(|(index, _)| devices[index].id.clone()),
+        devices,
+        selected,
+    })
+}
+
+fn emit<S: serde::Serialize + Clone>(app_handle: &tauri::AppHandle, event: &str, payload: S) {
+    if let Err(e) = app_handle.emit(event, payload) {
+        eprintln!("Failed to emit {} event: {}", event, e);
+    }
+}
+
+/// Compares `current` with `previous`, emits the device events and reports
+/// whether new sessions will now record from a different device.
+fn report_changes(app_handle: &tauri::AppHandle, previous: &Snapshot, current: &Snapshot) -> bool {
+    let (added, removed) = diff(&previous.devices, &current.devices);
+    if !added.is_empty() || !removed.is_empty() {
+        println!(
+            "Audio input devices changed: added {:?}, removed {:?}",
+            added.iter().map(|d| &d.name).collect::<Vec<_>>(),
+            removed.iter().map(|d| &d.name).collect::<Vec<_>>()
+        );
+        let payload = DevicesChanged {
+            added,
+            removed,
+            devices: current.devices.clone(),
+            active_device: current.active_device.clone(),
+        };
+        emit(app_handle, EVENT_AUDIO_DEVICES_CHANGED, payload);
+    }
+
+    // A selection the user just changed is not a device appearing or leaving.
+    if let Some(selected) = current
+        .selected
+        .as_deref()
+        .filter(|selected| previous.selected.as_deref() == Some(*selected))
+    {
+        if previous.selected_connected && !current.selected_connected {
+            let fallback = current
+                .active_device
+                .as_ref()
+                .and_then(|id| current.devices.iter().find(|d| &d.id == id))
+                .map(|d| d.name.clone());
+            devices::report_fallback(
+                app_handle,
+                selected,
+                fallback,
+                format!("Selected device '{}' was disconnected", selected),
+            );
+        } else if !previous.selected_connected && current.selected_connected {
+            println!("Selected input device '{}' is connected again", selected);
+            let restored = current
+                .active_device
+                .as_ref()
+                .and_then(|id| current.devices.iter().find(|d| &d.id == id));
+            if let Some(device) = restored {
+                emit(app_handle, EVENT_AUDIO_DEVICE_RESTORED, device.clone());
+            }
+        }
+    }
+
+    previous.active_device != current.active_device
+}
+
+/// Starts the background watcher. `on_active_device_changed` runs on the
+/// watcher thread whenever new sessions would record from a different device.
+/// No polls happen while `is_capturing` returns true.
+pub(crate) fn spawn<F, C>(
+    app_handle: tauri::AppHandle,
+    on_active_device_changed: F,
+    is_capturing: C,
+) where
+    F: Fn(&tauri::AppHandle) + Send + 'static,
+    C: Fn(&tauri::AppHandle) -> bool + Send + 'static,
+{
+    let spawned = thread::Builder::new()
+        .name("audio-device-watcher".to_string())
+        .spawn(move || {
+            let mut previous = take_snapshot(&app_handle).unwrap_or_else(|e| {
+                eprintln!("Audio device watcher could not list devices: {}", e);
+                Snapshot::default()
+            });
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                if is_capturing(&app_handle) {
+                    continue;
+                }
+                let current = match take_snapshot(&app_handle) {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        eprintln!("Audio device watcher could not list devices: {}", e);
+                        continue;
+                    }
+                };
+                if report_changes(&app_handle, &previous, &current) {
+                    println!(
+                        "Input device for new sessions is now {:?}",
+                        current.active_device
+                    );
+                    on_active_device_changed(&app_handle);
+                }
+                previous = current;
+            }
+        });
+    if let Err(e) = spawned {
+        eprintln!("Failed to start audio device watcher: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: format!("host:{}", id),
+            name: id.to_string(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_added_and_removed_devices() {
+        let previous = vec![device("Built-in"), device("Headset")];
+        let current = vec![device("Built-in"), device("USB Interface")];
+        let (added, removed) = diff(&previous, &current);
+        assert_eq!(added, vec![device("USB Interface")]);
+        assert_eq!(removed, vec![device("Headset")]);
+    }
+
+    #[test]
+    fn unchanged_list_has_empty_diff() {
+        let devices = vec![device("Built-in"), device("Headset")];
+        let (added, removed) = diff(&devices, &devices);
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+}
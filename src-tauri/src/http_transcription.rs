@@ -0,0 +1,557 @@
+//! OpenAI-compatible transcription provider: posts recordings to a
+//! `/v1/audio/transcriptions` endpoint, as served by OpenAI, gateways in
+//! front of it and self-hosted Whisper servers.
+//!
+//! Providers with a streaming URL also transcribe while the user speaks. That
+//! protocol is plain chunked HTTP: every chunk is its own POST of mono 16-bit
+//! little-endian PCM, tied to the others by a stream ID and sequence number,
+//! and the last one is marked `final=true`.
+
+use crate::streaming_transcription::{PartialTranscript, StreamChunk};
+use crate::transcription::{
+    HealthStatus, ProviderFuture, ProviderHealth, ProviderKind, TranscriptSegment,
+    TranscriptionProvider, TranscriptionRequest, TranscriptionResponse,
+};
+use std::time::{Duration, Instant};
+
+const TRANSCRIPTIONS_PATH: &str = "audio/transcriptions";
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request options for the transcription endpoint.
+/// Stored under [`crate::settings::TRANSCRIPTION_SETTINGS_KEY`] for the
+/// backend provider.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct TranscriptionSettings {
+    pub(crate) model: String,
+    /// ISO-639-1 code; the server detects the language when unset.
+    pub(crate) language: Option<String>,
+    /// Text that steers spelling and style, such as names or jargon.
+    pub(crate) prompt: Option<String>,
+    /// Sent as a bearer token when set.
+    pub(crate) api_key: Option<String>,
+    pub(crate) timeout_secs: u64,
+    /// `verbose_json` adds the language and timed segments; servers or
+    /// models that only support `json` still return the text.
+    pub(crate) response_format: String,
+    /// Full URL of the server's streaming endpoint for partial transcripts.
+    /// The provider does not stream when unset.
+    pub(crate) streaming_url: Option<String>,
+}
+
+impl Default for TranscriptionSettings {
+    fn default() -> Self {
+        Self {
+            model: "whisper-1".to_string(),
+            language: None,
+            prompt: None,
+            api_key: None,
+            timeout_secs: 60,
+            response_format: "verbose_json".to_string(),
+            streaming_url: None,
+        }
+    }
+}
+
+/// An extra OpenAI-compatible provider, such as a team's own gateway.
+/// Stored as a list under [`crate::settings::TRANSCRIPTION_PROVIDERS_KEY`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct HttpProviderConfig {
+    pub(crate) id: String,
+    pub(crate) url: String,
+    #[serde(flatten)]
+    pub(crate) settings: TranscriptionSettings,
+}
+
+/// The transcriptions endpoint under `backend_url`. Accepts the server root,
+/// its `/v1` prefix or the full endpoint URL.
+pub(crate) fn endpoint_url(backend_url: &str) -> Result<url::Url, String> {
+    let trimmed = backend_url.trim().trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Err("No backend URL configured".to_string());
+    }
+    let full = if trimmed.ends_with("/v1/audio/transcriptions") {
+        trimmed.to_string()
+    } else if trimmed.ends_with("/v1") {
+        format!("{}/{}", trimmed, TRANSCRIPTIONS_PATH)
+    } else {
+        format!("{}/v1/{}", trimmed, TRANSCRIPTIONS_PATH)
+    };
+    let url = url::Url::parse(&full).map_err(|e| format!("Invalid backend URL: {}", e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(format!("Unsupported URL scheme: {}", scheme)),
+    }
+}
+
+/// The streaming endpoint, taken as given.
+fn streaming_endpoint_url(url: &str) -> Result<url::Url, String> {
+    let url = url::Url::parse(url.trim()).map_err(|e| format!("Invalid streaming URL: {}", e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(format!("Unsupported URL scheme: {}", scheme)),
+    }
+}
+
+/// The `/v1/models` listing next to a transcriptions endpoint, used as a
+/// cheap request for health checks.
+fn models_url(endpoint: &url::Url) -> url::Url {
+    let mut url = endpoint.clone();
+    let path = url.path().trim_end_matches(TRANSCRIPTIONS_PATH).to_string();
+    url.set_path(&format!("{}models", path));
+    url
+}
+
+/// The message from an OpenAI-style `{"error": {"message": ...}}` body, or
+/// the start of the body as text.
+pub(crate) fn error_message(body: &str) -> String {
+    #[derive(serde::Deserialize)]
+    struct ErrorBody {
+        error: ErrorDetail,
+    }
+    #[derive(serde::Deserialize)]
+    struct ErrorDetail {
+        message: String,
+    }
+
+    match serde_json::from_str::<ErrorBody>(body) {
+        Ok(parsed) => parsed.error.message,
+        Err(_) => body.trim().chars().take(200).collect(),
+    }
+}
+
+/// Reads `json` and `verbose_json` responses; segment times are seconds.
+fn parse_response(body: &str) -> Result<TranscriptionResponse, String> {
+    #[derive(serde::Deserialize)]
+    struct ApiResponse {
+        text: String,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        segments: Vec<ApiSegment>,
+    }
+    #[derive(serde::Deserialize)]
+    struct ApiSegment {
+        start: f64,
+        end: f64,
+        text: String,
+    }
+
+    let parsed: ApiResponse =
+        serde_json::from_str(body).map_err(|e| format!("Invalid transcription response: {}", e))?;
+    let to_ms = |secs: f64| (secs.max(0.0) * 1000.0).round() as u64;
+    Ok(TranscriptionResponse {
+        text: parsed.text.trim().to_string(),
+        language: parsed.language.filter(|language| !language.is_empty()),
+        segments: parsed
+            .segments
+            .into_iter()
+            .map(|segment| TranscriptSegment {
+                start_ms: to_ms(segment.start),
+                end_ms: to_ms(segment.end),
+                text: segment.text.trim().to_string(),
+            })
+            .collect(),
+        ..TranscriptionResponse::default()
+    })
+}
+
+pub(crate) struct HttpProvider {
+    id: String,
+    http: reqwest::Client,
+    /// Invalid URLs are kept as errors so health checks can report them.
+    endpoint: Result<url::Url, String>,
+    /// Set when a streaming URL is configured; an invalid one fails the
+    /// first chunk, which ends the stream.
+    streaming_endpoint: Option<Result<url::Url, String>>,
+    settings: TranscriptionSettings,
+}
+
+impl HttpProvider {
+    pub(crate) fn new(id: &str, url: &str, settings: TranscriptionSettings) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs.max(1)))
+            .build()
+            .unwrap_or_default();
+        let streaming_endpoint = settings
+            .streaming_url
+            .as_deref()
+            .filter(|url| !url.trim().is_empty())
+            .map(streaming_endpoint_url);
+        Self {
+            id: id.to_string(),
+            http,
+            endpoint: endpoint_url(url),
+            streaming_endpoint,
+            settings,
+        }
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.settings.api_key.as_deref().filter(|k| !k.is_empty()) {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    /// Uploads a WAV recording and returns its transcript.
+    async fn post(&self, request: TranscriptionRequest) -> Result<TranscriptionResponse, String> {
+        let endpoint = self.endpoint.clone()?;
+        let file = reqwest::multipart::Part::bytes(request.wav)
+            .file_name("recording.wav")
+            .mime_str("audio/wav")
+            .map_err(|e| format!("Failed to build upload: {}", e))?;
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", file)
+            .text("model", self.settings.model.clone())
+            .text("response_format", self.settings.response_format.clone());
+        let language = request.language.or_else(|| self.settings.language.clone());
+        if let Some(language) = language.filter(|l| !l.is_empty()) {
+            form = form.text("language", language);
+        }
+        let prompt = request.prompt.or_else(|| self.settings.prompt.clone());
+        if let Some(prompt) = prompt.filter(|p| !p.is_empty()) {
+            form = form.text("prompt", prompt);
+        }
+
+        let response = self
+            .authorize(self.http.post(endpoint.clone()).multipart(form))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    format!("Transcription request to {} timed out", endpoint)
+                } else {
+                    format!("Transcription request to {} failed: {}", endpoint, e)
+                }
+            })?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read transcription response: {}", e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "Transcription failed ({}): {}",
+                status.as_u16(),
+                error_message(&body)
+            ));
+        }
+        parse_response(&body)
+    }
+
+    /// Posts one chunk of a recording in progress to the streaming endpoint.
+    async fn post_chunk(&self, chunk: StreamChunk) -> Result<PartialTranscript, String> {
+        let mut endpoint = self
+            .streaming_endpoint
+            .clone()
+            .unwrap_or_else(|| Err("No streaming URL configured".to_string()))?;
+        {
+            let mut query = endpoint.query_pairs_mut();
+            query
+                .append_pair("stream_id", &chunk.stream_id)
+                .append_pair("seq", &chunk.seq.to_string())
+                .append_pair("sample_rate", &chunk.sample_rate.to_string())
+                .append_pair("final", if chunk.is_final { "true" } else { "false" });
+            if let Some(language) = self.settings.language.as_deref().filter(|l| !l.is_empty()) {
+                query.append_pair("language", language);
+            }
+        }
+
+        let body: Vec<u8> = chunk.pcm.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let request = self
+            .http
+            .post(endpoint.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(body);
+        let response = self.authorize(request).send().await.map_err(|e| {
+            if e.is_timeout() {
+                format!("Streaming request to {} timed out", endpoint)
+            } else {
+                format!("Streaming request to {} failed: {}", endpoint, e)
+            }
+        })?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read streaming response: {}", e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "Streaming transcription failed ({}): {}",
+                status.as_u16(),
+                error_message(&body)
+            ));
+        }
+        serde_json::from_str(&body).map_err(|e| format!("Invalid streaming response: {}", e))
+    }
+
+    async fn check(&self) -> ProviderHealth {
+        let health = |status, detail: Option<String>, latency_ms| ProviderHealth {
+            provider: self.id.clone(),
+            kind: ProviderKind::OpenAiCompatible,
+            status,
+            detail,
+            latency_ms,
+        };
+        let endpoint = match &self.endpoint {
+            Ok(endpoint) => endpoint,
+            Err(e) => return health(HealthStatus::Misconfigured, Some(e.clone()), None),
+        };
+
+        let started_at = Instant::now();
+        let result = self
+            .authorize(self.http.get(models_url(endpoint)))
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await;
+        let latency_ms = Some(started_at.elapsed().as_millis() as u64);
+        match result {
+            Ok(response) => {
+                let status = response.status();
+                // Servers without a model listing still answered, so they are up.
+                let health_status = if status.as_u16() == 401 || status.as_u16() == 403 {
+                    HealthStatus::Unauthorized
+                } else if status.is_server_error() {
+                    HealthStatus::ServerError
+                } else {
+                    HealthStatus::Ok
+                };
+                let detail = (health_status != HealthStatus::Ok)
+                    .then(|| format!("{} answered {}", endpoint, status.as_u16()));
+                health(health_status, detail, latency_ms)
+            }
+            Err(e) if e.is_timeout() => health(HealthStatus::Timeout, None, None),
+            Err(e) if e.is_connect() => {
+                health(HealthStatus::ConnectionFailed, Some(e.to_string()), None)
+            }
+            Err(e) => health(HealthStatus::NetworkError, Some(e.to_string()), None),
+        }
+    }
+}
+
+impl TranscriptionProvider for HttpProvider {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAiCompatible
+    }
+
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+    ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+        Box::pin(self.post(request))
+    }
+
+    fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+        Box::pin(self.check())
+    }
+
+    fn supports_streaming(&self) -> bool {
+        self.streaming_endpoint.is_some()
+    }
+
+    fn stream_chunk(
+        &self,
+        chunk: StreamChunk,
+    ) -> ProviderFuture<'_, Result<PartialTranscript, String>> {
+        Box::pin(self.post_chunk(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers one request with `status` and `body`, and hands back the raw
+    /// request it received.
+    fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn endpoint_is_appended_to_backend_url() {
+        let expected = "http://localhost:5555/api/v1/audio/transcriptions";
+        for backend_url in [
+            "http://localhost:5555/api",
+            "http://localhost:5555/api/",
+            "http://localhost:5555/api/v1",
+            expected,
+        ] {
+            assert_eq!(endpoint_url(backend_url).unwrap().as_str(), expected);
+        }
+        assert!(endpoint_url("").is_err());
+        assert!(endpoint_url("ftp://localhost").is_err());
+    }
+
+    #[test]
+    fn only_providers_with_a_streaming_url_stream() {
+        let provider = |streaming_url: Option<&str>| {
+            HttpProvider::new(
+                "backend",
+                "http://localhost:5555/api",
+                TranscriptionSettings {
+                    streaming_url: streaming_url.map(str::to_string),
+                    ..TranscriptionSettings::default()
+                },
+            )
+        };
+        assert!(!provider(None).supports_streaming());
+        assert!(!provider(Some(" ")).supports_streaming());
+        assert!(provider(Some("http://localhost:5555/stream")).supports_streaming());
+        assert!(streaming_endpoint_url("ws://localhost/stream").is_err());
+    }
+
+    fn request(wav: &[u8]) -> TranscriptionRequest {
+        TranscriptionRequest {
+            wav: wav.to_vec(),
+            ..TranscriptionRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn uploads_wav_and_returns_transcript() {
+        let (url, server) = serve_once("200 OK", r#"{"text":" hello world "}"#);
+        let settings = TranscriptionSettings {
+            language: Some("en".to_string()),
+            api_key: Some("secret".to_string()),
+            ..TranscriptionSettings::default()
+        };
+        let provider = HttpProvider::new("backend", &url, settings);
+
+        let response = provider.transcribe(request(b"RIFFfake")).await.unwrap();
+        assert_eq!(response.text, "hello world");
+        assert!(response.segments.is_empty());
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/audio/transcriptions HTTP/1.1"));
+        assert!(request
+            .to_ascii_lowercase()
+            .contains("authorization: bearer secret"));
+        assert!(request.contains("filename=\"recording.wav\""));
+        assert!(request.contains("RIFFfake"));
+        assert!(request.contains("name=\"model\"\r\n\r\nwhisper-1"));
+        assert!(request.contains("name=\"response_format\"\r\n\r\nverbose_json"));
+        assert!(request.contains("name=\"language\"\r\n\r\nen"));
+        assert!(!request.contains("name=\"prompt\""));
+    }
+
+    #[test]
+    fn verbose_response_has_language_and_segments() {
+        let response = parse_response(
+            r#"{"text":"Hi there. Bye.","language":"english","duration":2.5,
+                "segments":[{"id":0,"start":0.0,"end":1.24,"text":" Hi there."},
+                            {"id":1,"start":1.24,"end":2.5,"text":" Bye."}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.language.as_deref(), Some("english"));
+        assert_eq!(
+            response.segments,
+            vec![
+                TranscriptSegment {
+                    start_ms: 0,
+                    end_ms: 1_240,
+                    text: "Hi there.".to_string(),
+                },
+                TranscriptSegment {
+                    start_ms: 1_240,
+                    end_ms: 2_500,
+                    text: "Bye.".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_error_message_is_reported() {
+        let (url, server) = serve_once(
+            "400 Bad Request",
+            r#"{"error":{"message":"Unsupported file format"}}"#,
+        );
+        let provider = HttpProvider::new("backend", &url, TranscriptionSettings::default());
+
+        let error = provider.transcribe(request(&[])).await.unwrap_err();
+        server.join().unwrap();
+        assert_eq!(error, "Transcription failed (400): Unsupported file format");
+    }
+
+    #[tokio::test]
+    async fn health_check_classifies_responses() {
+        let (url, server) = serve_once("200 OK", r#"{"data":[]}"#);
+        let provider = HttpProvider::new("backend", &format!("{}/api", url), Default::default());
+        let health = provider.health().await;
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /api/v1/models HTTP/1.1"));
+        assert_eq!(health.status, HealthStatus::Ok);
+        assert!(health.latency_ms.is_some());
+
+        let (url, server) = serve_once("401 Unauthorized", "{}");
+        let provider = HttpProvider::new("gateway", &url, Default::default());
+        assert_eq!(provider.health().await.status, HealthStatus::Unauthorized);
+        server.join().unwrap();
+
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let provider = HttpProvider::new("gateway", &url, Default::default());
+        assert_eq!(
+            provider.health().await.status,
+            HealthStatus::ConnectionFailed
+        );
+
+        let provider = HttpProvider::new("gateway", "", Default::default());
+        assert_eq!(provider.health().await.status, HealthStatus::Misconfigured);
+    }
+}
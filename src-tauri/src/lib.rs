@@ -1,18 +1,41 @@
+mod activation;
 mod audio;
+mod capture_format;
+mod chunking;
+mod cue;
+mod devices;
+mod diagnostics;
+mod dsp;
+mod hotplug;
+mod http_transcription;
+mod local_transcription;
+mod loudness;
+mod preroll;
+mod resample;
+mod sample_convert;
 mod screenshot;
+mod session;
+mod settings;
+mod source;
 mod state;
+mod streaming_transcription;
+mod transcription;
+mod vad;
+mod wav;
 
-use cpal::traits::{DeviceTrait, HostTrait};
-use crossbeam_channel::unbounded;
+use cpal::traits::DeviceTrait;
 use rodio::Sink;
 use serde_json::json;
-use state::{AppStateRef, AudioConfig, AudioConfigRef, RecorderState, RecordingFlag};
+use state::{
+    ActiveRecording, AppStateRef, LocalEngineRef, RecorderState, RecordingStartedAtRef,
+    RecordingStopRef, WarmMicrophoneRef,
+};
 use std::fs::File;
 use std::io::BufReader;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use tauri::menu::{MenuBuilder, MenuItem};
+use tauri::menu::{CheckMenuItem, MenuBuilder, MenuItem};
 use tauri::tray::TrayIconBuilder;
 use tauri::Manager;
 use tauri::{path::BaseDirectory, Emitter};
@@ -26,44 +49,132 @@ fn emit_state_change(app_handle: &tauri::AppHandle, new_state: RecorderState) {
     }
 }
 
-fn create_wav_memory(pcm_data: &[i16], channels: u16, sample_rate: u32) -> Result<Vec<u8>, String> {
-    let bits_per_sample: u16 = 16;
-    let bytes_per_sample = bits_per_sample / 8;
-    let block_align = channels * bytes_per_sample;
-    let byte_rate = sample_rate * u32::from(block_align);
+fn emit_recording_level(app_handle: &tauri::AppHandle, level: audio::AudioLevel) {
+    if let Err(e) = app_handle.emit("recording_level", &level) {
+        eprintln!("Failed to emit recording_level event: {}", e);
+    }
+}
 
-    let pcm_data_bytes: Vec<u8> = pcm_data
-        .iter()
-        .flat_map(|&sample| sample.to_le_bytes())
-        .collect();
-    let data_size = pcm_data_bytes.len() as u32;
+fn emit_recording_error(app_handle: &tauri::AppHandle, device_name: &str, reason: &str) {
+    let payload = json!({
+        "device": device_name,
+        "reason": reason,
+    });
+    if let Err(e) = app_handle.emit("recording_error", payload) {
+        eprintln!("Failed to emit recording_error event: {}", e);
+    }
+}
 
-    if data_size == 0 {
-        println!("Warning: Creating WAV from empty PCM data.");
+fn report_capture_overflow(
+    app_handle: &tauri::AppHandle,
+    dropped_samples: u64,
+    capacity_samples: usize,
+) {
+    if dropped_samples == 0 {
+        return;
     }
 
-    let file_size = 36 + data_size;
-    let mut wav_data = Vec::with_capacity(44 + pcm_data_bytes.len());
-
-    wav_data.extend_from_slice(b"RIFF");
-    wav_data.extend_from_slice(&file_size.to_le_bytes());
-    wav_data.extend_from_slice(b"WAVE");
-    wav_data.extend_from_slice(b"fmt ");
-    wav_data.extend_from_slice(&16u32.to_le_bytes());
-    wav_data.extend_from_slice(&1u16.to_le_bytes());
-    wav_data.extend_from_slice(&channels.to_le_bytes());
-    wav_data.extend_from_slice(&sample_rate.to_le_bytes());
-    wav_data.extend_from_slice(&byte_rate.to_le_bytes());
-    wav_data.extend_from_slice(&block_align.to_le_bytes());
-    wav_data.extend_from_slice(&bits_per_sample.to_le_bytes());
-    wav_data.extend_from_slice(b"data");
-    wav_data.extend_from_slice(&data_size.to_le_bytes());
-    wav_data.extend_from_slice(&pcm_data_bytes);
-
-    Ok(wav_data)
+    eprintln!(
+        "Capture buffer overflowed: {} samples dropped (capacity {} samples)",
+        dropped_samples, capacity_samples
+    );
+    let payload = json!({
+        "dropped_samples": dropped_samples,
+        "capacity_samples": capacity_samples,
+    });
+    if let Err(e) = app_handle.emit("recording_overflow", payload) {
+        eprintln!("Failed to emit recording_overflow event: {}", e);
+    }
 }
 
-fn play_sound_rodio(app_handle: &tauri::AppHandle, sound_name: &str) {
+fn emit_recording_diagnostics(
+    app_handle: &tauri::AppHandle,
+    diagnostics: &diagnostics::RecordingDiagnostics,
+) {
+    println!("Recording diagnostics: {:?}", diagnostics);
+    if let Err(e) = app_handle.emit("recording_diagnostics", diagnostics) {
+        eprintln!("Failed to emit recording_diagnostics event: {}", e);
+    }
+    if diagnostics.usable {
+        return;
+    }
+    if let Err(e) = app_handle.emit("recording_quality_warning", diagnostics) {
+        eprintln!("Failed to emit recording_quality_warning event: {}", e);
+    }
+}
+
+fn hands_free_settings(app_handle: &tauri::AppHandle) -> Option<audio::HandsFreeSettings> {
+    settings::read::<audio::HandsFreeSettings>(app_handle, settings::HANDS_FREE_SETTINGS_KEY)
+        .filter(|hands_free| hands_free.enabled)
+}
+
+/// Activation mode for `shortcut` and the hybrid tap threshold. Hands-free
+/// sessions always behave as toggle so the release never ends them.
+fn shortcut_activation(
+    app_handle: &tauri::AppHandle,
+    shortcut: activation::RecordingShortcut,
+) -> (activation::ActivationMode, Duration) {
+    let activation_settings: activation::ActivationSettings =
+        settings::read(app_handle, settings::SHORTCUT_ACTIVATION_KEY).unwrap_or_default();
+    let mode = if hands_free_settings(app_handle).is_some() {
+        activation::ActivationMode::Toggle
+    } else {
+        activation_settings.mode_for(shortcut)
+    };
+    (mode, activation_settings.tap_threshold())
+}
+
+const TRAY_ID: &str = "murmur_tray";
+
+/// Keeps the tray tooltip honest about whether the microphone is open while idle.
+fn update_tray_microphone_indicator(app_handle: &tauri::AppHandle, warm: bool) {
+    let Some(tray) = app_handle.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let tooltip = if warm {
+        "Murmur (microphone is on for pre-roll)"
+    } else {
+        "Murmur"
+    };
+    if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+        eprintln!("Failed to update tray tooltip: {}", e);
+    }
+}
+
+/// Opens or closes the warm microphone on the current input device and
+/// persists the choice. Any previously open warm stream is closed first.
+async fn set_warm_microphone(
+    app_handle: &tauri::AppHandle,
+    warm_microphone: &WarmMicrophoneRef,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut warm_settings: preroll::WarmMicrophoneSettings =
+        settings::read(app_handle, settings::WARM_MICROPHONE_KEY).unwrap_or_default();
+
+    let mut warm_guard = warm_microphone.lock().await;
+    *warm_guard = None;
+    update_tray_microphone_indicator(app_handle, false);
+    if enabled {
+        let input = devices::resolve_input_device(app_handle)?;
+        let capture = capture_format::resolve_for_session(app_handle, &input)?;
+        let warm =
+            preroll::WarmMicrophone::start(input.device, capture, warm_settings.preroll_ms)?;
+        *warm_guard = Some(Arc::new(warm));
+        update_tray_microphone_indicator(app_handle, true);
+    }
+    drop(warm_guard);
+
+    if warm_settings.enabled != enabled {
+        warm_settings.enabled = enabled;
+        settings::write(app_handle, settings::WARM_MICROPHONE_KEY, &warm_settings)?;
+    }
+    Ok(())
+}
+
+/// Plays a bundled sound without blocking. The returned timing is filled in
+/// once playback starts, so capture can keep the sound out of recordings.
+fn play_sound_rodio(app_handle: &tauri::AppHandle, sound_name: &str) -> cue::CueTiming {
+    let timing = cue::CueTiming::default();
     let sound_path = match app_handle.path().resolve(
         format!("assets/sounds/{}", sound_name),
         BaseDirectory::Resource,
@@ -71,31 +182,40 @@ fn play_sound_rodio(app_handle: &tauri::AppHandle, sound_name: &str) {
         Ok(path) => path,
         Err(e) => {
             eprintln!("Failed to resolve sound path for {}: {}", sound_name, e);
-            return;
+            timing.mark(Duration::ZERO);
+            return timing;
         }
     };
 
-    thread::spawn(move || match rodio::OutputStream::try_default() {
-        Ok((_stream, stream_handle)) => match File::open(&sound_path) {
-            Ok(file) => {
-                let file = BufReader::new(file);
-                match rodio::Decoder::new(file) {
-                    Ok(source) => {
-                        let sink = Sink::try_new(&stream_handle).unwrap();
-                        sink.set_volume(0.5);
-                        sink.append(source);
-                        sink.sleep_until_end();
-                        println!("Played sound: {:?}", sound_path);
-                    }
-                    Err(e) => {
-                        eprintln!("Error decoding sound file {:?}: {}", sound_path, e)
+    let timing_thread = timing.clone();
+    thread::spawn(move || {
+        match rodio::OutputStream::try_default() {
+            Ok((_stream, stream_handle)) => match File::open(&sound_path) {
+                Ok(file) => {
+                    let file = BufReader::new(file);
+                    match rodio::Decoder::new(file) {
+                        Ok(source) => {
+                            let duration = cue::sound_duration(&sound_path).unwrap_or_default();
+                            let sink = Sink::try_new(&stream_handle).unwrap();
+                            sink.set_volume(0.5);
+                            sink.append(source);
+                            timing_thread.mark(duration);
+                            sink.sleep_until_end();
+                            println!("Played sound: {:?}", sound_path);
+                        }
+                        Err(e) => {
+                            eprintln!("Error decoding sound file {:?}: {}", sound_path, e)
+                        }
                     }
                 }
-            }
-            Err(e) => eprintln!("Error opening sound file {:?}: {}", sound_path, e),
-        },
-        Err(e) => eprintln!("Error getting default audio output stream: {}", e),
+                Err(e) => eprintln!("Error opening sound file {:?}: {}", sound_path, e),
+            },
+            Err(e) => eprintln!("Error getting default audio output stream: {}", e),
+        }
+        // No-op after a successful start; otherwise nothing was heard.
+        timing_thread.mark(Duration::ZERO);
     });
+    timing
 }
 
 #[tauri::command]
@@ -122,7 +242,12 @@ async fn perform_clipboard_paste(text: String, app_handle: tauri::AppHandle) ->
         "Executing perform_clipboard_paste command for text: '{}'",
         text
     );
+    paste_text(&app_handle, text).await
+}
 
+/// Pastes `text` into the focused application through the clipboard, then
+/// restores what the clipboard held before.
+async fn paste_text(app_handle: &tauri::AppHandle, text: String) -> Result<(), String> {
     use tauri_plugin_clipboard_manager::ClipboardExt;
     let previous_clipboard = app_handle.clipboard().read_text().unwrap_or_default();
     println!("Saved current clipboard content");
@@ -427,6 +552,22 @@ async fn test_backend_connection(url: String) -> Result<String, String> {
     }
 }
 
+#[tauri::command]
+async fn get_transcription_providers(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<transcription::ProviderInfo>, String> {
+    Ok(transcription_registry(&app_handle).list())
+}
+
+/// Checks every configured transcription provider, for display next to the
+/// backend connection test.
+#[tauri::command]
+async fn check_transcription_providers(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<transcription::ProviderHealth>, String> {
+    Ok(transcription_registry(&app_handle).health_checks().await)
+}
+
 #[derive(serde::Serialize)]
 struct AudioDevice {
     id: String,
@@ -445,59 +586,672 @@ async fn get_audio_input_devices() -> Result<Vec<AudioDevice>, String> {
         .and_then(|d| d.name().ok())
         .unwrap_or_default();
 
-    let devices = host
-        .input_devices()
-        .map_err(|e| format!("Failed to enumerate input devices: {}", e))?;
+    let audio_devices = devices::list_input_devices(&host)?
+        .into_iter()
+        .map(|input| AudioDevice {
+            is_default: input.name == default_name,
+            id: input.id,
+            name: input.name,
+        })
+        .collect();
+
+    Ok(audio_devices)
+}
+
+#[tauri::command]
+async fn get_selected_audio_device(app_handle: tauri::AppHandle) -> Result<String, String> {
+    use cpal::traits::{DeviceTrait, HostTrait};
 
-    let mut audio_devices = Vec::new();
+    let host = cpal::default_host();
+    let selected: Option<String> =
+        settings::read(&app_handle, settings::SELECTED_AUDIO_DEVICE_KEY);
+    if let Some(selected) = selected.filter(|id| !id.is_empty()) {
+        // Older settings stored the device name; report the current ID when it resolves.
+        return Ok(devices::find_input_device(&host, &selected)
+            .map(|input| input.id)
+            .unwrap_or(selected));
+    }
 
-    for device in devices {
-        if let Ok(name) = device.name() {
-            let device_id = format!("device_{}", audio_devices.len());
-            audio_devices.push(AudioDevice {
-                id: device_id,
-                name: name.clone(),
-                is_default: name == default_name,
+    let default_name = host
+        .default_input_device()
+        .ok_or_else(|| "No default input device found".to_string())?
+        .name()
+        .map_err(|e| format!("Failed to get device name: {}", e))?;
+    devices::find_input_device(&host, &default_name)
+        .map(|input| input.id)
+        .ok_or_else(|| "No default input device found".to_string())
+}
+
+/// Signals the capture session in progress, if any, to stop.
+async fn stop_recording(recording_stop: &RecordingStopRef) {
+    if let Some(active) = recording_stop.lock().await.take() {
+        active.stop.stop();
+    }
+}
+
+/// The recording shortcut whose sessions deliver to `output_mode`.
+fn recording_shortcut(output_mode: session::OutputMode) -> activation::RecordingShortcut {
+    match output_mode {
+        session::OutputMode::Chat => activation::RecordingShortcut::Recorder,
+        session::OutputMode::Clipboard => activation::RecordingShortcut::Clipboard,
+    }
+}
+
+/// Reports session progress to the frontend.
+struct AppSessionEvents(tauri::AppHandle);
+
+impl session::SessionEvents for AppSessionEvents {
+    fn state_changed(&self, state: RecorderState) {
+        emit_state_change(&self.0, state);
+    }
+
+    fn level(&self, level: audio::AudioLevel) {
+        emit_recording_level(&self.0, level);
+    }
+
+    fn capture_failed(&self, source: &str, error: &str) {
+        emit_recording_error(&self.0, source, error);
+    }
+
+    fn overflow(&self, dropped_samples: u64, capacity_samples: usize) {
+        report_capture_overflow(&self.0, dropped_samples, capacity_samples);
+    }
+
+    fn diagnostics(&self, diagnostics: &diagnostics::RecordingDiagnostics) {
+        emit_recording_diagnostics(&self.0, diagnostics);
+    }
+
+    fn no_speech(&self, session_id: u64) {
+        let payload = json!({ "sessionId": session_id });
+        if let Err(e) = self.0.emit("recording_no_speech", &payload) {
+            eprintln!("Failed to emit recording_no_speech event: {}", e);
+        }
+    }
+
+    fn partial_transcript(
+        &self,
+        session_id: u64,
+        partial: &streaming_transcription::PartialTranscript,
+    ) {
+        let payload = json!({
+            "stable": partial.stable,
+            "unstable": partial.unstable,
+            "sessionId": session_id,
+        });
+        if let Err(e) = self.0.emit("transcript_partial", &payload) {
+            eprintln!("Failed to emit transcript_partial event: {}", e);
+        }
+    }
+
+    fn end_cue(&self) {
+        play_sound_rodio(&self.0, "record-end.mp3");
+    }
+
+    /// Clipboard sessions, and chat sessions using any provider but the
+    /// backend, are transcribed here and resolve once the transcript has been
+    /// delivered. Other chat sessions go to the chat window, which transcribes
+    /// them and comes to the front; long ones are cut at pauses first so each
+    /// upload stays under the size limit.
+    fn deliver(
+        &self,
+        delivery: session::Delivery,
+    ) -> transcription::ProviderFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            let registry = transcription_registry(&self.0);
+            let uses_backend = registry
+                .active()
+                .is_some_and(|provider| provider.id() == transcription::BACKEND_PROVIDER_ID);
+            if delivery.output_mode == session::OutputMode::Clipboard || !uses_backend {
+                transcribe_and_deliver(self.0.clone(), delivery, registry).await;
+                return Ok(());
+            }
+
+            let Some(main_window) = self.0.get_webview_window("main") else {
+                emit_processing_error(
+                    &self.0,
+                    delivery.session_id,
+                    "audio_transfer",
+                    "Main window not found",
+                );
+                return Err("Main window not found".to_string());
+            };
+
+            if let Err(e) = main_window.show() {
+                eprintln!("Failed to show main window: {}", e);
+            }
+            if let Err(e) = main_window.set_focus() {
+                eprintln!("Failed to focus main window: {}", e);
+            }
+
+            let chunking: chunking::ChunkingSettings =
+                settings::read(&self.0, settings::TRANSCRIPTION_CHUNKING_KEY).unwrap_or_default();
+            let chunks: Vec<Vec<u8>> = chunking::split_on_silence(&delivery.audio, &chunking)
+                .into_iter()
+                .map(|chunk| chunk.wav)
+                .collect();
+            println!(
+                "[session {}] Sending {} chunk(s) to the chat window.",
+                delivery.session_id,
+                chunks.len()
+            );
+            let payload = json!({
+                "chunks": chunks,
+                "maxParallel": chunking.max_parallel.max(1),
+                "diagnostics": delivery.diagnostics,
+                "isClipboardMode": false,
+                "sessionId": delivery.session_id,
             });
+            main_window
+                .emit("audio_data_available", payload)
+                .map_err(|e| format!("Failed to emit audio_data_available: {}", e))
+        })
+    }
+}
+
+fn emit_processing_error(
+    app_handle: &tauri::AppHandle,
+    session_id: u64,
+    stage: &str,
+    message: &str,
+) {
+    let payload = json!({
+        "stage": stage,
+        "message": message,
+        "sessionId": session_id,
+    });
+    if let Err(e) = app_handle.emit("processing_error", &payload) {
+        eprintln!("Failed to emit processing_error event: {}", e);
+    }
+}
+
+/// Transcription providers from settings: the backend, the local engine and
+/// any extra OpenAI-compatible providers, with the active one selected. Local
+/// mode always uses the on-device engine; builds without it fail each
+/// recording with an error instead of sending the audio anywhere.
+fn transcription_registry(app_handle: &tauri::AppHandle) -> transcription::ProviderRegistry {
+    let backend_url: String =
+        settings::read(app_handle, settings::BACKEND_URL_KEY).unwrap_or_default();
+    let backend_settings =
+        settings::read(app_handle, settings::TRANSCRIPTION_SETTINGS_KEY).unwrap_or_default();
+    let local_settings =
+        settings::read(app_handle, settings::LOCAL_TRANSCRIPTION_SETTINGS_KEY).unwrap_or_default();
+    let extra_providers: Vec<http_transcription::HttpProviderConfig> =
+        settings::read(app_handle, settings::TRANSCRIPTION_PROVIDERS_KEY).unwrap_or_default();
+    let chunking =
+        settings::read(app_handle, settings::TRANSCRIPTION_CHUNKING_KEY).unwrap_or_default();
+
+    let mut providers: Vec<Box<dyn transcription::TranscriptionProvider>> = vec![
+        Box::new(http_transcription::HttpProvider::new(
+            transcription::BACKEND_PROVIDER_ID,
+            &backend_url,
+            backend_settings,
+        )),
+        Box::new(local_transcription::LocalProvider::new(
+            app_handle.state::<LocalEngineRef>().inner().clone(),
+            local_settings,
+        )),
+    ];
+    for config in extra_providers {
+        providers.push(Box::new(http_transcription::HttpProvider::new(
+            &config.id,
+            &config.url,
+            config.settings,
+        )));
+    }
+
+    let selected: Option<String> =
+        settings::read(app_handle, settings::TRANSCRIPTION_PROVIDER_KEY);
+    let local_mode =
+        settings::read::<bool>(app_handle, settings::USE_LOCAL_MODE_KEY).unwrap_or(false);
+    transcription::ProviderRegistry::new(providers, selected.as_deref(), local_mode)
+        .with_chunking(chunking)
+}
+
+/// Transcribes a session with the active provider, then pastes the text
+/// (clipboard mode) or hands it to the chat window. Clipboard sessions never
+/// need a webview; windows only hear about the outcome.
+async fn transcribe_and_deliver(
+    app_handle: tauri::AppHandle,
+    delivery: session::Delivery,
+    registry: transcription::ProviderRegistry,
+) {
+    let session_id = delivery.session_id;
+    let clipboard_mode = delivery.output_mode == session::OutputMode::Clipboard;
+    let transcript = match registry.transcribe(&delivery.audio).await {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            eprintln!("[session {}] Transcription failed: {}", session_id, e);
+            emit_processing_error(&app_handle, session_id, "transcription", &e);
+            return;
         }
+    };
+    println!(
+        "[session {}] {} transcribed {} ms of audio in {} ms ({} characters).",
+        session_id,
+        transcript.provider,
+        transcript.timing.audio_ms,
+        transcript.timing.processing_ms,
+        transcript.text.chars().count()
+    );
+
+    if !clipboard_mode {
+        if let Some(main_window) = app_handle.get_webview_window("main") {
+            if let Err(e) = main_window.show() {
+                eprintln!("Failed to show main window: {}", e);
+            }
+            if let Err(e) = main_window.set_focus() {
+                eprintln!("Failed to focus main window: {}", e);
+            }
+        }
+    }
+    let payload = json!({
+        "text": transcript.text,
+        "language": transcript.language,
+        "segments": transcript.segments,
+        "timing": transcript.timing,
+        "provider": transcript.provider,
+        "diagnostics": delivery.diagnostics,
+        "isClipboardMode": clipboard_mode,
+        "sessionId": session_id,
+    });
+    if let Err(e) = app_handle.emit("transcription_completed", &payload) {
+        eprintln!("Failed to emit transcription_completed event: {}", e);
     }
 
-    Ok(audio_devices)
+    if !clipboard_mode || transcript.text.trim().is_empty() {
+        return;
+    }
+    if let Err(e) = paste_text(&app_handle, transcript.text).await {
+        eprintln!("[session {}] Paste failed: {}", session_id, e);
+        emit_processing_error(&app_handle, session_id, "clipboard_paste", &e);
+    }
+}
+
+/// Session settings from the store for audio captured in the given format.
+fn session_config(
+    app_handle: &tauri::AppHandle,
+    output_mode: session::OutputMode,
+    native_sample_rate: u32,
+    native_channels: u16,
+    input_gain_db: f32,
+    start_cue: cue::StartCueSettings,
+) -> session::SessionConfig {
+    let output_sample_rate = settings::read::<u32>(app_handle, settings::OUTPUT_SAMPLE_RATE_KEY)
+        .filter(|rate| *rate > 0)
+        .unwrap_or(resample::DEFAULT_OUTPUT_SAMPLE_RATE);
+
+    session::SessionConfig {
+        output_mode,
+        format: session::AudioFormat {
+            native_sample_rate,
+            native_channels,
+            output_sample_rate,
+            output_channels: 1,
+        },
+        input_gain_db,
+        start_cue,
+        dsp: settings::read(app_handle, settings::DSP_SETTINGS_KEY).unwrap_or_default(),
+        vad: settings::read(app_handle, settings::VAD_SETTINGS_KEY).unwrap_or_default(),
+        loudness: settings::read(app_handle, settings::LOUDNESS_SETTINGS_KEY).unwrap_or_default(),
+    }
+}
+
+/// Streams the session's audio for partial transcripts when enabled and the
+/// active transcription provider supports it. The on-device engine does not,
+/// so local mode never streams.
+fn with_streaming(
+    app_handle: &tauri::AppHandle,
+    recording_session: session::RecordingSession,
+) -> session::RecordingSession {
+    let streaming: streaming_transcription::StreamingSettings =
+        settings::read(app_handle, settings::STREAMING_TRANSCRIPTION_KEY).unwrap_or_default();
+    if !streaming.enabled {
+        return recording_session;
+    }
+    match transcription_registry(app_handle).streaming() {
+        Some(provider) => {
+            let chunk = Duration::from_millis(streaming.chunk_ms.max(100));
+            recording_session.stream_to(provider, chunk)
+        }
+        None => recording_session,
+    }
+}
+
+/// Runs the session in the background.
+async fn spawn_recording_session(
+    app_handle: &tauri::AppHandle,
+    recording_session: session::RecordingSession,
+    source: Box<dyn source::AudioSource>,
+) {
+    let recording_session = with_streaming(app_handle, recording_session);
+    let events = Arc::new(AppSessionEvents(app_handle.clone()));
+    let app_state = app_handle.state::<AppStateRef>().inner().clone();
+    tokio::spawn(recording_session.run(source, events, app_state));
+}
+
+/// Opens the input device and starts a session whose audio goes to
+/// `output_mode`. The recorder must already be in `Recording`, with `stop`
+/// stored in [`RecordingStopRef`].
+async fn start_recording_session(
+    app_handle: &tauri::AppHandle,
+    output_mode: session::OutputMode,
+    stop: audio::StopSignal,
+) -> Result<(), String> {
+    let start_cue_settings: cue::StartCueSettings =
+        settings::read(app_handle, settings::START_CUE_KEY).unwrap_or_default();
+    let start_cue = play_sound_rodio(app_handle, "record-start.mp3");
+
+    let input = devices::resolve_input_device(app_handle)?;
+    let capture = capture_format::resolve_for_session(app_handle, &input)
+        .map_err(|e| format!("Error getting input config: {}", e))?;
+    let native_sample_rate = capture.config.sample_rate().0;
+    let captured_channels = capture.captured_channels();
+
+    let config = session_config(
+        app_handle,
+        output_mode,
+        native_sample_rate,
+        captured_channels,
+        devices::input_gain_db(app_handle, &input.id),
+        start_cue_settings,
+    );
+    let recording_session = session::RecordingSession::new(config, start_cue, stop);
+
+    let buffer_ms = settings::read::<u64>(app_handle, settings::CAPTURE_BUFFER_MS_KEY)
+        .filter(|ms| *ms > 0)
+        .unwrap_or(audio::DEFAULT_CAPTURE_BUFFER_MS);
+    let warm_microphone = app_handle.state::<WarmMicrophoneRef>();
+    let source = source::CpalSource {
+        device: input.device,
+        capacity_samples: audio::capture_buffer_samples(
+            native_sample_rate,
+            captured_channels,
+            buffer_ms,
+        ),
+        options: audio::CaptureOptions {
+            hands_free: hands_free_settings(app_handle),
+            warm_microphone: warm_microphone.lock().await.clone(),
+            cue_gate: recording_session.cue_gate(),
+            captured_from: recording_session.captured_from(),
+            buffer_frames: capture.buffer_frames,
+            input_channel: capture.input_channel,
+        },
+        config: capture.config,
+    };
+
+    spawn_recording_session(app_handle, recording_session, Box::new(source)).await;
+    Ok(())
 }
 
+/// Debug aid: runs a WAV file (or raw 16-bit PCM when `sample_rate` and
+/// `channels` are given) through the recording pipeline as if it had been
+/// spoken. `realtime` plays it at natural speed so the stop shortcut and level
+/// meter behave as they would live.
 #[tauri::command]
-async fn get_selected_audio_device() -> Result<String, String> {
-    // For now, return the default device
-    // In a more complete implementation, this would read from settings
-    use cpal::traits::{DeviceTrait, HostTrait};
+async fn replay_audio_file(
+    path: String,
+    output_mode: Option<session::OutputMode>,
+    realtime: Option<bool>,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let path = std::path::PathBuf::from(path);
+    let source = match (sample_rate, channels) {
+        (Some(sample_rate), Some(channels)) => {
+            source::FileSource::open_raw(&path, sample_rate, channels)?
+        }
+        (None, None) => source::FileSource::open_wav(&path)?,
+        _ => return Err("Raw PCM needs both a sample rate and a channel count".to_string()),
+    }
+    .realtime(realtime.unwrap_or(false));
+
+    let output_mode = output_mode.unwrap_or(session::OutputMode::Chat);
+    let app_state = app_handle.state::<AppStateRef>().inner().clone();
+    let stop = audio::StopSignal::default();
+    {
+        let mut current_app_state = app_state.lock().await;
+        if *current_app_state != RecorderState::Idle {
+            return Err(format!(
+                "Cannot replay while the recorder is {:?}",
+                *current_app_state
+            ));
+        }
+        *current_app_state = RecorderState::Recording;
+        *app_handle.state::<RecordingStopRef>().lock().await = Some(ActiveRecording {
+            shortcut: recording_shortcut(output_mode),
+            stop: stop.clone(),
+        });
+    }
+    emit_state_change(&app_handle, RecorderState::Recording);
+
+    println!("Replaying {} ({:?})", path.display(), output_mode);
+    let config = session_config(
+        &app_handle,
+        output_mode,
+        source.sample_rate(),
+        source.channels(),
+        0.0,
+        cue::StartCueSettings {
+            mode: cue::StartCueMode::Off,
+            ..Default::default()
+        },
+    );
+    let recording_session =
+        session::RecordingSession::new(config, cue::CueTiming::default(), stop);
+    spawn_recording_session(&app_handle, recording_session, Box::new(source)).await;
+    Ok(())
+}
+
+/// Handles a press or release of either recording shortcut.
+async fn handle_recording_shortcut(
+    app_handle: tauri::AppHandle,
+    output_mode: session::OutputMode,
+    pressed: bool,
+) {
+    let shortcut = recording_shortcut(output_mode);
+    let app_state = app_handle.state::<AppStateRef>().inner().clone();
+    let recording_stop = app_handle.state::<RecordingStopRef>().inner().clone();
+    let recording_started_at = app_handle.state::<RecordingStartedAtRef>().inner().clone();
+    let mut current_app_state = app_state.lock().await;
+
+    if pressed && *current_app_state == RecorderState::Idle {
+        println!("{:?} shortcut pressed: Idle -> Recording", output_mode);
+        *current_app_state = RecorderState::Recording;
+        // Stored before any setup, so a release that arrives while the
+        // device is opening still stops the session.
+        let stop = audio::StopSignal::default();
+        *recording_stop.lock().await = Some(ActiveRecording {
+            shortcut,
+            stop: stop.clone(),
+        });
+        emit_state_change(&app_handle, RecorderState::Recording);
+        drop(current_app_state);
+        *recording_started_at.lock().await = Some(std::time::Instant::now());
+
+        if let Err(e) = start_recording_session(&app_handle, output_mode, stop).await {
+            eprintln!("Error: {}", e);
+            recording_stop.lock().await.take();
+            *app_state.lock().await = RecorderState::Idle;
+            emit_state_change(&app_handle, RecorderState::Idle);
+        }
+        return;
+    }
+
+    let action = if pressed { "pressed" } else { "released" };
+    let current_state = *current_app_state;
+    drop(current_app_state);
+    if current_state != RecorderState::Recording {
+        println!(
+            "{:?} shortcut {}: State is {:?} (Ignoring)",
+            output_mode, action, current_state
+        );
+        return;
+    }
+    let Some(started_by) = recording_stop
+        .lock()
+        .await
+        .as_ref()
+        .map(|active| active.shortcut)
+    else {
+        println!(
+            "{:?} shortcut {}: Recording is already stopping (Ignoring)",
+            output_mode, action
+        );
+        return;
+    };
+
+    let event = if pressed {
+        activation::ShortcutEvent::Press
+    } else {
+        let held_for = recording_started_at
+            .lock()
+            .await
+            .map(|pressed_at| pressed_at.elapsed())
+            .unwrap_or_default();
+        activation::ShortcutEvent::Release { held_for }
+    };
+    // The session follows the mode of the shortcut that started it.
+    let (activation_mode, tap_threshold) = shortcut_activation(&app_handle, started_by);
+    if !activation::stops_recording(started_by, activation_mode, shortcut, event, tap_threshold) {
+        println!(
+            "{:?} shortcut {}: Recording stays on ({:?} session in {:?} mode, {:?})",
+            output_mode, action, started_by, activation_mode, event
+        );
+        return;
+    }
+    println!(
+        "{:?} shortcut {}: Recording -> Processing ({:?} mode)",
+        output_mode, action, activation_mode
+    );
+    stop_recording(&recording_stop).await;
+}
+
+/// Reopens an open warm microphone on the current input device and its
+/// saved capture format.
+async fn reopen_warm_microphone(app_handle: &tauri::AppHandle) {
+    let warm_microphone = app_handle.state::<WarmMicrophoneRef>().inner().clone();
+    if warm_microphone.lock().await.is_none() {
+        return;
+    }
+    if let Err(e) = set_warm_microphone(app_handle, &warm_microphone, true).await {
+        eprintln!("Failed to reopen warm microphone: {}", e);
+    }
+}
+
+#[tauri::command]
+async fn set_selected_audio_device(
+    device_id: String,
+    gain_db: Option<f32>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if device_id.is_empty() {
+        println!("Clearing selected audio device, using system default");
+        settings::remove(&app_handle, settings::SELECTED_AUDIO_DEVICE_KEY)?;
+        reopen_warm_microphone(&app_handle).await;
+        return Ok(());
+    }
 
     let host = cpal::default_host();
-    match host.default_input_device() {
-        Some(device) => device
-            .name()
-            .map_err(|e| format!("Failed to get device name: {}", e)),
-        None => Err("No default input device found".to_string()),
+    let input = devices::find_input_device(&host, &device_id)
+        .ok_or_else(|| format!("Audio input device '{}' not found", device_id))?;
+
+    settings::write(&app_handle, settings::SELECTED_AUDIO_DEVICE_KEY, &input.id)?;
+    if let Some(gain_db) = gain_db {
+        devices::set_input_gain_db(&app_handle, &input.id, gain_db)?;
     }
+    println!("Selected audio device: {} ({})", input.name, input.id);
+    reopen_warm_microphone(&app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_audio_device_gain(
+    device_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<f32, String> {
+    Ok(devices::input_gain_db(&app_handle, &device_id))
+}
+
+/// Saves the software input gain for a device; returns the gain after clamping.
+#[tauri::command]
+async fn set_audio_device_gain(
+    device_id: String,
+    gain_db: f32,
+    app_handle: tauri::AppHandle,
+) -> Result<f32, String> {
+    let gain_db = devices::set_input_gain_db(&app_handle, &device_id, gain_db)?;
+    println!("Input gain for {}: {:+.1} dB", device_id, gain_db);
+    Ok(gain_db)
+}
+
+#[tauri::command]
+async fn get_capture_formats(
+    device_id: String,
+) -> Result<capture_format::SupportedCaptureFormats, String> {
+    let host = cpal::default_host();
+    let input = devices::find_input_device(&host, &device_id)
+        .ok_or_else(|| format!("Audio input device '{}' not found", device_id))?;
+    capture_format::supported_formats(&input.device)
+}
+
+#[tauri::command]
+async fn get_capture_format(
+    device_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<capture_format::CaptureFormat, String> {
+    Ok(capture_format::saved_format(&app_handle, &device_id))
+}
+
+/// Saves the sample rate, buffer size and input channel for a device after
+/// checking the device supports them. Unset fields use the device default.
+#[tauri::command]
+async fn set_capture_format(
+    device_id: String,
+    format: capture_format::CaptureFormat,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let host = cpal::default_host();
+    let input = devices::find_input_device(&host, &device_id)
+        .ok_or_else(|| format!("Audio input device '{}' not found", device_id))?;
+    capture_format::save_format(&app_handle, &input, format.clone())?;
+    println!("Capture format for {}: {:?}", input.id, format);
+    // A warm stream still open in the old format would never match a session.
+    reopen_warm_microphone(&app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_audio_device_priority(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
+    Ok(settings::read(&app_handle, settings::AUDIO_DEVICE_PRIORITY_KEY).unwrap_or_default())
 }
 
 #[tauri::command]
-async fn set_selected_audio_device(device_name: String) -> Result<(), String> {
-    // For now, this is a placeholder
-    // In a more complete implementation, this would:
-    // 1. Validate the device exists
-    // 2. Store the selection in settings
-    // 3. Update the audio recording to use the selected device
-
-    println!("Selected audio device: {}", device_name);
+async fn set_audio_device_priority(
+    device_ids: Vec<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    // Devices in the list may be unplugged right now, so IDs are stored unvalidated.
+    let mut priority: Vec<String> = Vec::with_capacity(device_ids.len());
+    for id in device_ids {
+        if !id.is_empty() && !priority.contains(&id) {
+            priority.push(id);
+        }
+    }
+
+    settings::write(&app_handle, settings::AUDIO_DEVICE_PRIORITY_KEY, &priority)?;
+    println!("Audio device priority updated: {:?}", priority);
     Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 #[tokio::main]
 pub async fn run() {
-    let audio_config = AudioConfigRef::new(tokio::sync::Mutex::new(AudioConfig::new()));
     let app_state = AppStateRef::new(tokio::sync::Mutex::new(RecorderState::Idle));
-    let recording_flag = RecordingFlag::new(AtomicBool::new(false));
+    let recording_stop = RecordingStopRef::new(tokio::sync::Mutex::new(None));
+    let recording_started_at = RecordingStartedAtRef::new(tokio::sync::Mutex::new(None));
+    let warm_microphone = WarmMicrophoneRef::new(tokio::sync::Mutex::new(None));
+    let local_engine = LocalEngineRef::default();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_process::init())
@@ -516,20 +1270,32 @@ pub async fn run() {
             check_accessibility_permission,
             request_accessibility_permission,
             test_backend_connection,
+            get_transcription_providers,
+            check_transcription_providers,
             get_audio_input_devices,
             get_selected_audio_device,
-            set_selected_audio_device
+            set_selected_audio_device,
+            get_audio_device_gain,
+            set_audio_device_gain,
+            get_capture_formats,
+            get_capture_format,
+            set_capture_format,
+            get_audio_device_priority,
+            set_audio_device_priority,
+            replay_audio_file
         ])
-        .manage(audio_config.clone())
         .manage(app_state.clone())
-        .manage(recording_flag.clone())
+        .manage(recording_stop.clone())
+        .manage(recording_started_at.clone())
+        .manage(warm_microphone.clone())
+        .manage(local_engine)
         .setup(move |app| {
             // ---- BEGIN STORE SETUP ----
             let store_file_name = "settings.json";
             match app.store(store_file_name) {
                 Ok(store) => {
-                    let use_local_mode_key = "use_local_mode";
-                    let backend_url_key = "backend_url";
+                    let use_local_mode_key = settings::USE_LOCAL_MODE_KEY;
+                    let backend_url_key = settings::BACKEND_URL_KEY;
                     let mut defaults_were_set = false;
 
                     // Set default for use_local_mode if not present
@@ -574,15 +1340,23 @@ pub async fn run() {
             let show_chat_i = MenuItem::with_id(&app_handle_tray, "show_chat", "Show Chat", true, None::<&str>)?;
             let show_settings_i = MenuItem::with_id(&app_handle_tray, "show_settings", "Settings", true, None::<&str>)?;
             let quit_i = MenuItem::with_id(&app_handle_tray, "quit", "Exit", true, None::<&str>)?;
+            let warm_microphone_enabled = settings::read::<preroll::WarmMicrophoneSettings>(&app_handle_tray, settings::WARM_MICROPHONE_KEY)
+                .unwrap_or_default()
+                .enabled;
+            let warm_microphone_i = CheckMenuItem::with_id(&app_handle_tray, "warm_microphone", "Keep Microphone Warm (pre-roll)", true, warm_microphone_enabled, None::<&str>)?;
 
             let tray_menu = MenuBuilder::new(&app_handle_tray)
                 .item(&show_chat_i)
                 .item(&show_settings_i)
                 .separator()
+                .item(&warm_microphone_i)
+                .separator()
                 .item(&quit_i)
                 .build()?;
 
-            let _tray = TrayIconBuilder::new()
+            let warm_microphone_menu = warm_microphone.clone();
+            let warm_microphone_item_menu = warm_microphone_i.clone();
+            let _tray = TrayIconBuilder::with_id(TRAY_ID)
                 .icon(app.default_window_icon().cloned().ok_or("Failed to get default window icon")?)
                 .tooltip("Murmur")
                 .menu(&tray_menu)
@@ -640,6 +1414,19 @@ pub async fn run() {
                                 }
                             }
                         }
+                        "warm_microphone" => {
+                            let app_handle = app.clone();
+                            let warm_microphone = warm_microphone_menu.clone();
+                            let warm_microphone_item = warm_microphone_item_menu.clone();
+                            tauri::async_runtime::spawn(async move {
+                                // The check mark has already toggled by the time the event arrives.
+                                let enabled = warm_microphone_item.is_checked().unwrap_or(false);
+                                if let Err(e) = set_warm_microphone(&app_handle, &warm_microphone, enabled).await {
+                                    eprintln!("Failed to toggle warm microphone: {}", e);
+                                    let _ = warm_microphone_item.set_checked(false);
+                                }
+                            });
+                        }
                         "quit" => {
                             println!("Exit requested from tray menu.");
                             app.exit(0);
@@ -649,6 +1436,32 @@ pub async fn run() {
                 })
                 .build(app)?;
 
+            if warm_microphone_enabled {
+                let app_handle = app.handle().clone();
+                let warm_microphone = warm_microphone.clone();
+                let warm_microphone_item = warm_microphone_i.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = set_warm_microphone(&app_handle, &warm_microphone, true).await {
+                        eprintln!("Failed to open warm microphone: {}", e);
+                        let _ = warm_microphone_item.set_checked(false);
+                    }
+                });
+            }
+
+            // Moves the warm microphone along when devices come and go.
+            hotplug::spawn(
+                app.handle().clone(),
+                |app_handle| {
+                    tauri::async_runtime::block_on(reopen_warm_microphone(app_handle));
+                },
+                |app_handle| {
+                    // A contended lock means the recorder is changing state.
+                    let app_state = app_handle.state::<AppStateRef>();
+                    app_state
+                        .try_lock()
+                        .map_or(true, |state| *state == RecorderState::Recording)
+                },
+            );
 
             #[cfg(desktop)]
             {
@@ -667,436 +1480,57 @@ pub async fn run() {
                     eprintln!("Failed to get main window during setup.");
                 }
 
-                let audio_config_handler = audio_config.clone();
-                let app_state_handler = app_state.clone();
-                let recording_flag_handler = recording_flag.clone();
-
                 app.handle().plugin(
                     tauri_plugin_global_shortcut::Builder::new()
                         .with_handler(move |app, shortcut, event| {
-                            let audio_config_clone = audio_config_handler.clone();
-                            let app_state_clone = app_state_handler.clone();
-                            let recording_flag_clone = recording_flag_handler.clone();
                             let app_handle_clone = app.clone();
                             let shortcut_clone = shortcut.clone();
 
                             tokio::spawn(async move {
-                                if shortcut_clone == recorder_shortcut {
-                                    let mut current_app_state = app_state_clone.lock().await;
-
-                                    match event.state() {
-                                        ShortcutState::Pressed => {
-                                                match *current_app_state {
-                                                    RecorderState::Idle => {
-                                                        println!("Shortcut Pressed: Idle -> Recording");
-                                                        *current_app_state = RecorderState::Recording;
-                                                        emit_state_change(&app_handle_clone, RecorderState::Recording);
-                                                        drop(current_app_state);
-
-                                                        let _main_window_visible = match app_handle_clone.get_webview_window("main") {
-                                                            Some(main_window) => main_window.is_visible().unwrap_or(false),
-                                                            None => {
-                                                                eprintln!("Main window not found during shortcut press check.");
-                                                                false
-                                                            }
-                                                        };
-
-                                                        play_sound_rodio(&app_handle_clone, "record-start.mp3");
-
-                                                        recording_flag_clone.store(false, Ordering::SeqCst);
-
-                                                        let host = cpal::default_host();
-                                                        let device = match host.default_input_device() {
-                                                            Some(d) => d,
-                                                            None => {
-                                                                eprintln!("Error: No input device available");
-                                                                let mut state = app_state_clone.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_clone, RecorderState::Idle);
-                                                                return;
-                                                            }
-                                                        };
-                                                        let config = match device.default_input_config() {
-                                                            Ok(c) => c,
-                                                            Err(e) => {
-                                                                eprintln!("Error getting default input config: {}", e);
-                                                                let mut state = app_state_clone.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_clone, RecorderState::Idle);
-                                                                return;
-                                                            }
-                                                        };
-
-                                                        let sample_format = config.sample_format();
-                                                        {
-                                                            let mut audio_config_guard = audio_config_clone.lock().await;
-                                                            audio_config_guard.sample_rate = config.sample_rate().0;
-                                                            audio_config_guard.channels = config.channels();
-                                                        }
-
-                                                        let (tx, rx) = unbounded::<Vec<i16>>();
-
-                                                        recording_flag_clone.store(true, Ordering::SeqCst);
-
-                                                        let flag_thread = recording_flag_clone.clone();
-                                                        thread::spawn(move || {
-                                                            println!("Recording thread started.");
-                                                            if let Err(err) = audio::record_audio_stream(
-                                                                flag_thread.clone(),
-                                                                tx,
-                                                                device,
-                                                                config,
-                                                                sample_format,
-                                                            ) {
-                                                                eprintln!("Recording error: {}", err);
-                                                                flag_thread.store(false, Ordering::SeqCst);
-                                                            }
-                                                            println!("Recording thread finished.");
-                                                        });
-
-                                                        let app_handle_post = app_handle_clone.clone();
-                                                        let audio_config_post = audio_config_clone.clone();
-                                                        let app_state_post = app_state_clone.clone();
-                                                        let recording_flag_post = recording_flag_clone.clone();
-
-                                                        tokio::spawn(async move {
-                                                            println!("Post-processing task spawned, waiting for recording flag...");
-
-                                                            while recording_flag_post.load(Ordering::SeqCst) {
-                                                                tokio::time::sleep(Duration::from_millis(50)).await;
-                                                            }
-                                                            println!("Post-processing task detected recording stopped.");
-
-                                                            let mut all_pcm_data = Vec::new();
-                                                            while let Ok(chunk) = rx.try_recv() {
-                                                                all_pcm_data.extend(chunk);
-                                                            }
-                                                            while let Ok(chunk) = rx.try_recv() {
-                                                                all_pcm_data.extend(chunk);
-                                                            }
-                                                            println!("Collected {} samples from channel.", all_pcm_data.len());
-
-                                                            let audio_config_guard = audio_config_post.lock().await;
-                                                            let sample_rate = audio_config_guard.sample_rate;
-                                                            let channels = audio_config_guard.channels;
-                                                            drop(audio_config_guard);
-
-                                                            if all_pcm_data.is_empty() {
-                                                                println!("Post-processing: Audio data is empty. Resetting state.");
-                                                                let mut state = app_state_post.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                                return;
-                                                            }
-
-                                                            let wav_data = match create_wav_memory(&all_pcm_data, channels, sample_rate) {
-                                                                Ok(data) => data,
-                                                                Err(e) => {
-                                                                    eprintln!("Failed to create WAV data: {}", e);
-                                                                    let mut state = app_state_post.lock().await;
-                                                                    *state = RecorderState::Idle;
-                                                                    emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                                    return;
-                                                                }
-                                                            };
-
-                                                            let data_size = wav_data.len().saturating_sub(44);
-                                                            let bytes_per_sample = 2u32;
-                                                            let duration_secs = if sample_rate > 0 && channels > 0 {
-                                                                data_size as f32 / (sample_rate * u32::from(channels) * bytes_per_sample) as f32
-                                                            } else { 0.0 };
-                                                            println!("Post-processing: Calculated duration: {:.2}s", duration_secs);
-
-                                                            if duration_secs > 1.0 {
-                                                                println!("Post-processing: Duration > 1s. Starting transcription.");
-                                                                {
-                                                                    let mut state = app_state_post.lock().await;
-                                                                    *state = RecorderState::Transcribing;
-                                                                    emit_state_change(&app_handle_post, RecorderState::Transcribing);
-                                                                }
-
-                                                                println!("Sending WAV audio data to frontend for transcription. Size: {} bytes", wav_data.len());
-                                                
-                                                if let Some(main_window) = app_handle_post.get_webview_window("main") {
-                                                    println!("Found main (AI interaction) window, sending audio data...");
-                                                    
-                                                    let wav_data_clone = wav_data.clone();
-                                                    tokio::spawn(async move {
-                                                        if let Err(e) = main_window.show() { eprintln!("Failed to show main window: {}", e); }
-                                                        if let Err(e) = main_window.set_focus() { eprintln!("Failed to focus main window: {}", e); }
-                                                        
-                                                        let payload = serde_json::json!({
-                                                            "data": wav_data_clone,
-                                                            "isClipboardMode": false
-                                                        });
-                                                        
-                                                        if let Err(e) = main_window.emit("audio_data_available", payload) {
-                                                            eprintln!("Failed to emit audio_data_available to main window: {}", e);
-                                                        } else {
-                                                            println!("Audio data sent to frontend successfully");
-                                                        }
-                                                    });
-                                                } else {
-                                                    eprintln!("Error: main window not found. Cannot send audio data.");
-                                                    if let Err(e_emit) = app_handle_post.emit("processing_error", &serde_json::json!({ 
-                                                        "stage": "audio_transfer", 
-                                                        "message": "Main window not found" 
-                                                    })) {
-                                                        eprintln!("Failed to emit processing_error event: {}", e_emit);
-                                                    }
-                                                }
-                                                            } else {
-                                                                println!("Post-processing: Duration <= 1s. Playing end sound and resetting state.");
-                                                                play_sound_rodio(&app_handle_post, "record-end.mp3");
-                                                            }
-
-                                                            {
-                                                                let mut state = app_state_post.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                            }
-                                                            println!("Post-processing task finished.");
-                                                        }); 
-
-                                                    }
-                                                    RecorderState::Recording | RecorderState::Transcribing => {
-                                                        println!("Shortcut Pressed: State is {:?} (Ignoring)", *current_app_state);
+                                let recording_output = if shortcut_clone == recorder_shortcut {
+                                    Some(session::OutputMode::Chat)
+                                } else if shortcut_clone == clipboard_shortcut {
+                                    Some(session::OutputMode::Clipboard)
+                                } else {
+                                    None
+                                };
+
+                                if let Some(output_mode) = recording_output {
+                                    handle_recording_shortcut(app_handle_clone, output_mode, event.state() == ShortcutState::Pressed).await;
+                                } else if shortcut_clone == ai_shortcut && event.state() == ShortcutState::Pressed {
+                                    println!("AI Shortcut (Alt+`) Pressed: Toggling Main (AI Interaction) Window");
+                                    if let Some(main_window) = app_handle_clone.get_webview_window("main") {
+                                        tokio::spawn(async move {
+                                            match main_window.is_visible() {
+                                                Ok(true) => {
+                                                    println!("Main window is visible, hiding it.");
+                                                    if let Err(e) = main_window.hide() {
+                                                        eprintln!("Failed to hide main window: {}", e);
                                                     }
                                                 }
-                                            }
-                                            ShortcutState::Released => {
-                                                let current_state = *current_app_state;
-                                                drop(current_app_state);
-
-                                                if let RecorderState::Recording = current_state {
-                                                    println!("Shortcut Released: Recording -> Processing...");
-                                                    println!("Setting recording flag to false.");
-                                                    recording_flag_clone.store(false, Ordering::SeqCst);
-                                                } else {
-                                                    println!("Shortcut Released: Not in Recording state (Ignoring)");
-                                                }
-                                            }
-                                        }
-                                } else if shortcut_clone == ai_shortcut {
-                                    if event.state() == ShortcutState::Pressed {
-                                        println!("AI Shortcut (Alt+`) Pressed: Toggling Main (AI Interaction) Window");
-                                        if let Some(main_window) = app_handle_clone.get_webview_window("main") {
-                                            tokio::spawn(async move {
-                                                match main_window.is_visible() {
-                                                    Ok(true) => {
-                                                        println!("Main window is visible, hiding it.");
-                                                        if let Err(e) = main_window.hide() {
-                                                            eprintln!("Failed to hide main window: {}", e);
-                                                        }
-                                                    }
-                                                    Ok(false) => {
-                                                        println!("Main window is not visible, showing and focusing it.");
-                                                        if let Err(e) = main_window.show() { 
-                                                            eprintln!("Failed to show main window: {}", e); 
-                                                        }
-                                                        if let Err(e) = main_window.set_focus() { 
-                                                            eprintln!("Failed to focus main window: {}", e); 
-                                                        }
+                                                Ok(false) => {
+                                                    println!("Main window is not visible, showing and focusing it.");
+                                                    if let Err(e) = main_window.show() { 
+                                                        eprintln!("Failed to show main window: {}", e); 
                                                     }
-                                                    Err(e) => {
-                                                        eprintln!("Failed to check main window visibility: {}. Assuming not visible and attempting to show.", e);
-                                                        if let Err(e_show) = main_window.show() { 
-                                                            eprintln!("Failed to show main window (fallback): {}", e_show); 
-                                                        }
-                                                        if let Err(e_focus) = main_window.set_focus() { 
-                                                            eprintln!("Failed to focus main window (fallback): {}", e_focus); 
-                                                        }
+                                                    if let Err(e) = main_window.set_focus() { 
+                                                        eprintln!("Failed to focus main window: {}", e); 
                                                     }
                                                 }
-                                            });
-                                        } else {
-                                            eprintln!("Main (AI Interaction) window not found in shortcut handler.");
-                                        }
-                                    }
-                                }
-                                else if shortcut_clone == clipboard_shortcut {
-                                    let mut current_app_state = app_state_clone.lock().await;
-
-                                    match event.state() {
-                                        ShortcutState::Pressed => {
-                                            match *current_app_state {
-                                                RecorderState::Idle => {
-                                                        println!("Clipboard Shortcut Pressed: Idle -> Recording");
-                                                        *current_app_state = RecorderState::Recording;
-                                                        emit_state_change(&app_handle_clone, RecorderState::Recording);
-                                                        drop(current_app_state);
-
-                                                        play_sound_rodio(&app_handle_clone, "record-start.mp3");
-
-                                                        recording_flag_clone.store(false, Ordering::SeqCst);
-
-                                                        let host = cpal::default_host();
-                                                        let device = match host.default_input_device() {
-                                                            Some(d) => d,
-                                                            None => {
-                                                                eprintln!("Error: No input device available");
-                                                                let mut state = app_state_clone.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_clone, RecorderState::Idle);
-                                                                return;
-                                                            }
-                                                        };
-                                                        let config = match device.default_input_config() {
-                                                            Ok(c) => c,
-                                                            Err(e) => {
-                                                                eprintln!("Error getting default input config: {}", e);
-                                                                let mut state = app_state_clone.lock().await;
-                                                                *state = RecorderState::Idle; // Revert state
-                                                                emit_state_change(&app_handle_clone, RecorderState::Idle);
-                                                                return;
-                                                            }
-                                                        };
-
-                                                        let sample_format = config.sample_format();
-                                                        {
-                                                            let mut audio_config_guard = audio_config_clone.lock().await;
-                                                            audio_config_guard.sample_rate = config.sample_rate().0;
-                                                            audio_config_guard.channels = config.channels();
-                                                        }
-
-                                                        let (tx, rx) = unbounded::<Vec<i16>>();
-
-                                                        recording_flag_clone.store(true, Ordering::SeqCst);
-
-                                                        let flag_thread = recording_flag_clone.clone();
-                                                        thread::spawn(move || {
-                                                            println!("Recording thread started for clipboard.");
-                                                            if let Err(err) = audio::record_audio_stream(
-                                                                flag_thread.clone(),
-                                                                tx,
-                                                                device,
-                                                                config,
-                                                                sample_format,
-                                                            ) {
-                                                                eprintln!("Recording error: {}", err);
-                                                                flag_thread.store(false, Ordering::SeqCst);
-                                                            }
-                                                            println!("Recording thread finished for clipboard.");
-                                                        });
-
-                                                        let app_handle_post = app_handle_clone.clone();
-                                                        let audio_config_post = audio_config_clone.clone();
-                                                        let app_state_post = app_state_clone.clone();
-                                                        let recording_flag_post = recording_flag_clone.clone();
-
-                                                        tokio::spawn(async move {
-                                                            println!("Clipboard post-processing task spawned, waiting for recording flag...");
-
-                                                            while recording_flag_post.load(Ordering::SeqCst) {
-                                                                tokio::time::sleep(Duration::from_millis(50)).await;
-                                                            }
-                                                            println!("Clipboard post-processing task detected recording stopped.");
-
-                                                            let mut all_pcm_data = Vec::new();
-                                                            while let Ok(chunk) = rx.try_recv() {
-                                                                all_pcm_data.extend(chunk);
-                                                            }
-                                                            while let Ok(chunk) = rx.try_recv() {
-                                                                all_pcm_data.extend(chunk);
-                                                            }
-                                                            println!("Collected {} samples from channel for clipboard.", all_pcm_data.len());
-
-                                                            let audio_config_guard = audio_config_post.lock().await;
-                                                            let sample_rate = audio_config_guard.sample_rate;
-                                                            let channels = audio_config_guard.channels;
-                                                            drop(audio_config_guard);
-
-                                                            if all_pcm_data.is_empty() {
-                                                                println!("Clipboard post-processing: Audio data is empty. Resetting state.");
-                                                                let mut state = app_state_post.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                                return;
-                                                            }
-
-                                                            let wav_data = match create_wav_memory(&all_pcm_data, channels, sample_rate) {
-                                                                Ok(data) => data,
-                                                                Err(e) => {
-                                                                    eprintln!("Failed to create WAV data for clipboard: {}", e);
-                                                                    let mut state = app_state_post.lock().await;
-                                                                    *state = RecorderState::Idle;
-                                                                    emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                                    return;
-                                                                }
-                                                            };
-
-                                                            let data_size = wav_data.len().saturating_sub(44);
-                                                            let bytes_per_sample = 2u32;
-                                                            let duration_secs = if sample_rate > 0 && channels > 0 {
-                                                                data_size as f32 / (sample_rate * u32::from(channels) * bytes_per_sample) as f32
-                                                            } else { 0.0 };
-                                                            println!("Clipboard post-processing: Calculated duration: {:.2}s", duration_secs);
-
-                                                            if duration_secs > 1.0 {
-                                                                println!("Clipboard post-processing: Duration > 1s. Starting transcription.");
-                                                                {
-                                                                    let mut state = app_state_post.lock().await;
-                                                                    *state = RecorderState::Transcribing;
-                                                                    emit_state_change(&app_handle_post, RecorderState::Transcribing);
-                                                                }
-
-                                                                println!("Sending clipboard WAV audio data to frontend. Size: {} bytes", wav_data.len());
-                                                                
-                                                                if let Some(main_window) = app_handle_post.get_webview_window("main") {
-                                                                    println!("Found main window, sending audio data for clipboard operation...");
-                                                                    
-                                                                    let payload = serde_json::json!({
-                                                                        "data": wav_data,
-                                                                        "isClipboardMode": true
-                                                                    });
-                                                                    
-                                                                    tokio::spawn(async move {
-                                                                        if let Err(e) = main_window.emit("audio_data_available", payload) {
-                                                                            eprintln!("Failed to emit audio_data_available to main window: {}", e);
-                                                                        } else {
-                                                                            println!("Audio data with clipboard flag sent to frontend");
-                                                                        }
-                                                                    });
-                                                                } else {
-                                                                    eprintln!("Error: main window not found. Cannot send audio data for clipboard.");
-                                                                    if let Err(e_emit) = app_handle_post.emit("processing_error", &serde_json::json!({ 
-                                                                        "stage": "audio_transfer", 
-                                                                        "message": "Main window not found" 
-                                                                    })) {
-                                                                        eprintln!("Failed to emit processing_error event: {}", e_emit);
-                                                                    }
-                                                                }
-                                                            } else {
-                                                                println!("Clipboard post-processing: Duration <= 1s. Playing end sound and resetting state.");
-                                                                play_sound_rodio(&app_handle_post, "record-end.mp3");
-                                                            }
-
-                                                            {
-                                                                let mut state = app_state_post.lock().await;
-                                                                *state = RecorderState::Idle;
-                                                                emit_state_change(&app_handle_post, RecorderState::Idle);
-                                                            }
-                                                            println!("Clipboard post-processing task finished.");
-                                                        }); // End of post-processing tokio::spawn
+                                                Err(e) => {
+                                                    eprintln!("Failed to check main window visibility: {}. Assuming not visible and attempting to show.", e);
+                                                    if let Err(e_show) = main_window.show() { 
+                                                        eprintln!("Failed to show main window (fallback): {}", e_show); 
                                                     }
-                                                    RecorderState::Recording | RecorderState::Transcribing => {
-                                                        println!("Clipboard Shortcut Pressed: State is {:?} (Ignoring)", *current_app_state);
+                                                    if let Err(e_focus) = main_window.set_focus() { 
+                                                        eprintln!("Failed to focus main window (fallback): {}", e_focus); 
                                                     }
                                                 }
                                             }
-                                            ShortcutState::Released => {
-                                                let current_state = *current_app_state;
-                                                drop(current_app_state);
-
-                                                if let RecorderState::Recording = current_state {
-                                                    println!("Clipboard Shortcut Released: Recording -> Processing...");
-                                                    println!("Setting recording flag to false for clipboard.");
-                                                    recording_flag_clone.store(false, Ordering::SeqCst);
-                                                } else {
-                                                    println!("Clipboard Shortcut Released: Not in Recording state (Ignoring)");
-                                                }
-                                            }
-                                        }
+                                        });
+                                    } else {
+                                        eprintln!("Main (AI Interaction) window not found in shortcut handler.");
+                                    }
                                 }
                             });
                         })
@@ -0,0 +1,426 @@
+//! On-device speech to text with a whisper.cpp (GGML) model, used instead of
+//! the backend when local mode is on. Runs on the CPU only, so no audio
+//! leaves the machine.
+//!
+//! The engine is compiled in with the `local-transcription` cargo feature,
+//! which builds whisper.cpp and needs cmake and a C++ toolchain.
+
+use crate::resample;
+use crate::state::LocalEngineRef;
+use crate::transcription::{
+    HealthStatus, ProviderFuture, ProviderHealth, ProviderKind, TranscriptSegment,
+    TranscriptionProvider, TranscriptionRequest, TranscriptionResponse, LOCAL_PROVIDER_ID,
+};
+use crate::wav;
+
+/// Sample rate whisper models are trained on.
+const MODEL_SAMPLE_RATE: u32 = 16_000;
+/// Upper bound for the automatic thread count; whisper.cpp gains little
+/// beyond this and the rest of the app stays responsive.
+const MAX_AUTO_THREADS: usize = 8;
+
+/// Stored under [`crate::settings::LOCAL_TRANSCRIPTION_SETTINGS_KEY`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct LocalTranscriptionSettings {
+    /// Path to a GGML whisper model, such as `ggml-base.en.bin`.
+    pub(crate) model_path: Option<String>,
+    /// Worker threads; 0 picks one per core up to a small limit.
+    pub(crate) threads: usize,
+    /// ISO-639-1 code, or unset or `auto` to detect the language.
+    pub(crate) language: Option<String>,
+}
+
+impl LocalTranscriptionSettings {
+    fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads;
+        }
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_AUTO_THREADS)
+    }
+
+    /// The language to force, or `None` to let the model detect it.
+    fn forced_language(&self) -> Option<&str> {
+        self.language
+            .as_deref()
+            .map(str::trim)
+            .filter(|language| !language.is_empty() && !language.eq_ignore_ascii_case("auto"))
+    }
+}
+
+/// Decodes a recording into the mono 16 kHz float samples whisper expects.
+fn model_input(wav_bytes: &[u8]) -> Result<Vec<f32>, String> {
+    let pcm = wav::read_pcm16(wav_bytes)?;
+    let mono = resample::convert_for_speech(
+        &pcm.samples,
+        pcm.sample_rate,
+        pcm.channels,
+        MODEL_SAMPLE_RATE,
+    );
+    Ok(mono
+        .into_iter()
+        .map(|sample| f32::from(sample) / 32_768.0)
+        .collect())
+}
+
+/// Trims segment texts, drops empty segments and joins the rest into the
+/// transcript text.
+fn tidy_segments(segments: Vec<TranscriptSegment>) -> (String, Vec<TranscriptSegment>) {
+    let segments: Vec<TranscriptSegment> = segments
+        .into_iter()
+        .map(|segment| TranscriptSegment {
+            text: segment.text.trim().to_string(),
+            ..segment
+        })
+        .filter(|segment| !segment.text.is_empty())
+        .collect();
+    let text = segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (text, segments)
+}
+
+/// Segments and the language whisper settled on.
+struct ModelOutput {
+    segments: Vec<TranscriptSegment>,
+    language: Option<String>,
+}
+
+/// Keeps the loaded model between recordings; loading takes far longer than
+/// transcribing a short clip.
+#[derive(Default)]
+pub(crate) struct LocalEngine {
+    #[cfg(feature = "local-transcription")]
+    model: Option<whisper::Model>,
+}
+
+impl LocalEngine {
+    /// Transcribes a WAV recording. Blocks for the length of inference, so
+    /// call it off the async runtime.
+    pub(crate) fn transcribe(
+        &mut self,
+        wav_bytes: &[u8],
+        settings: &LocalTranscriptionSettings,
+    ) -> Result<TranscriptionResponse, String> {
+        let model_path = settings
+            .model_path
+            .as_deref()
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| "No local transcription model configured".to_string())?;
+        let samples = model_input(wav_bytes)?;
+        if samples.is_empty() {
+            return Ok(TranscriptionResponse::default());
+        }
+        let output = self.run(
+            model_path,
+            &samples,
+            settings.thread_count(),
+            settings.forced_language(),
+        )?;
+        let (text, segments) = tidy_segments(output.segments);
+        Ok(TranscriptionResponse {
+            text,
+            language: output.language,
+            segments,
+            ..TranscriptionResponse::default()
+        })
+    }
+
+    #[cfg(feature = "local-transcription")]
+    fn run(
+        &mut self,
+        model_path: &str,
+        samples: &[f32],
+        threads: usize,
+        language: Option<&str>,
+    ) -> Result<ModelOutput, String> {
+        if self.model.as_ref().map(|model| model.path.as_str()) != Some(model_path) {
+            println!("Loading local transcription model from {}", model_path);
+            self.model = None;
+            self.model = Some(whisper::Model::load(model_path)?);
+        }
+        let model = self.model.as_ref().expect("model was just loaded");
+        model.transcribe(samples, threads, language)
+    }
+
+    #[cfg(not(feature = "local-transcription"))]
+    fn run(
+        &mut self,
+        _model_path: &str,
+        _samples: &[f32],
+        _threads: usize,
+        _language: Option<&str>,
+    ) -> Result<ModelOutput, String> {
+        Err(
+            "This build does not include local transcription; rebuild with the \
+             `local-transcription` feature"
+                .to_string(),
+        )
+    }
+}
+
+/// Transcribes with the shared [`LocalEngine`] on a blocking thread.
+pub(crate) struct LocalProvider {
+    engine: LocalEngineRef,
+    settings: LocalTranscriptionSettings,
+}
+
+impl LocalProvider {
+    pub(crate) fn new(engine: LocalEngineRef, settings: LocalTranscriptionSettings) -> Self {
+        Self { engine, settings }
+    }
+
+    fn check(&self) -> (HealthStatus, Option<String>) {
+        if !cfg!(feature = "local-transcription") {
+            return (
+                HealthStatus::Unavailable,
+                Some("Built without the `local-transcription` feature".to_string()),
+            );
+        }
+        let Some(model_path) = self
+            .settings
+            .model_path
+            .as_deref()
+            .filter(|p| !p.is_empty())
+        else {
+            return (
+                HealthStatus::Misconfigured,
+                Some("No local transcription model configured".to_string()),
+            );
+        };
+        match std::fs::metadata(model_path) {
+            Ok(metadata) if metadata.is_file() => (
+                HealthStatus::Ok,
+                Some(format!(
+                    "{} ({} MB)",
+                    model_path,
+                    metadata.len() / (1024 * 1024)
+                )),
+            ),
+            _ => (
+                HealthStatus::Misconfigured,
+                Some(format!("Model file not found: {}", model_path)),
+            ),
+        }
+    }
+}
+
+impl TranscriptionProvider for LocalProvider {
+    fn id(&self) -> &str {
+        LOCAL_PROVIDER_ID
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Local
+    }
+
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+    ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+        let engine = self.engine.clone();
+        let mut settings = self.settings.clone();
+        if let Some(language) = request.language {
+            settings.language = Some(language);
+        }
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                engine.blocking_lock().transcribe(&request.wav, &settings)
+            })
+            .await
+            .map_err(|e| format!("Local transcription task failed: {}", e))?
+        })
+    }
+
+    fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+        let (status, detail) = self.check();
+        Box::pin(async move {
+            ProviderHealth {
+                provider: LOCAL_PROVIDER_ID.to_string(),
+                kind: ProviderKind::Local,
+                status,
+                detail,
+                latency_ms: None,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "local-transcription")]
+mod whisper {
+    use super::ModelOutput;
+    use crate::transcription::TranscriptSegment;
+    use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+    pub(super) struct Model {
+        pub(super) path: String,
+        context: WhisperContext,
+    }
+
+    impl Model {
+        pub(super) fn load(path: &str) -> Result<Self, String> {
+            let mut parameters = WhisperContextParameters::default();
+            parameters.use_gpu(false);
+            let context = WhisperContext::new_with_params(path, parameters)
+                .map_err(|e| format!("Failed to load model '{}': {}", path, e))?;
+            Ok(Self {
+                path: path.to_string(),
+                context,
+            })
+        }
+
+        /// Each segment whisper found, in order.
+        pub(super) fn transcribe(
+            &self,
+            samples: &[f32],
+            threads: usize,
+            language: Option<&str>,
+        ) -> Result<ModelOutput, String> {
+            let mut state = self
+                .context
+                .create_state()
+                .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+            let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+            params.set_n_threads(threads as i32);
+            params.set_language(Some(language.unwrap_or("auto")));
+            params.set_translate(false);
+            params.set_no_context(true);
+            params.set_print_special(false);
+            params.set_print_progress(false);
+            params.set_print_realtime(false);
+            params.set_print_timestamps(false);
+
+            state
+                .full(params, samples)
+                .map_err(|e| format!("Local transcription failed: {}", e))?;
+            let segments = state
+                .full_n_segments()
+                .map_err(|e| format!("Failed to read transcript: {}", e))?;
+            let read_error =
+                |e: whisper_rs::WhisperError| format!("Failed to read transcript: {}", e);
+            // whisper.cpp reports segment times in hundredths of a second.
+            let to_ms = |centis: i64| centis.max(0) as u64 * 10;
+            let segments = (0..segments)
+                .map(|segment| {
+                    Ok(TranscriptSegment {
+                        start_ms: to_ms(state.full_get_segment_t0(segment).map_err(read_error)?),
+                        end_ms: to_ms(state.full_get_segment_t1(segment).map_err(read_error)?),
+                        text: state
+                            .full_get_segment_text_lossy(segment)
+                            .map_err(read_error)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let language = state
+                .full_lang_id_from_state()
+                .ok()
+                .and_then(whisper_rs::get_lang_str)
+                .map(str::to_string);
+            Ok(ModelOutput { segments, language })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_is_converted_to_mono_16k_floats() {
+        // 0.5 s of 48 kHz stereo with the left channel at half scale.
+        let pcm: Vec<i16> = (0..24_000).flat_map(|_| [16_384, 0]).collect();
+        let samples = model_input(&wav::write_pcm16(&pcm, 2, 48_000)).unwrap();
+
+        assert!(
+            (samples.len() as i64 - 8_000).abs() <= 2,
+            "{}",
+            samples.len()
+        );
+        let middle = samples[4_000];
+        assert!((middle - 0.25).abs() < 0.01, "{}", middle);
+    }
+
+    #[cfg(not(feature = "local-transcription"))]
+    #[test]
+    fn builds_without_the_engine_refuse_to_transcribe() {
+        let settings = LocalTranscriptionSettings {
+            model_path: Some("ggml-base.en.bin".to_string()),
+            ..LocalTranscriptionSettings::default()
+        };
+        let wav = wav::write_pcm16(&[0; 1_600], 1, 16_000);
+        let error = LocalEngine::default()
+            .transcribe(&wav, &settings)
+            .unwrap_err();
+        assert!(
+            error.contains("does not include local transcription"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn auto_language_and_thread_count() {
+        let mut settings = LocalTranscriptionSettings::default();
+        assert_eq!(settings.forced_language(), None);
+        assert!((1..=MAX_AUTO_THREADS).contains(&settings.thread_count()));
+
+        settings.language = Some(" AUTO ".to_string());
+        assert_eq!(settings.forced_language(), None);
+        settings.language = Some("de".to_string());
+        settings.threads = 3;
+        assert_eq!(settings.forced_language(), Some("de"));
+        assert_eq!(settings.thread_count(), 3);
+    }
+
+    #[test]
+    fn segments_are_trimmed_and_joined() {
+        let segment = |start_ms, text: &str| TranscriptSegment {
+            start_ms,
+            end_ms: start_ms + 1_000,
+            text: text.to_string(),
+        };
+        let (text, segments) = tidy_segments(vec![
+            segment(0, " Hello there."),
+            segment(1_000, "  "),
+            segment(2_000, " Bye."),
+        ]);
+        assert_eq!(text, "Hello there. Bye.");
+        assert_eq!(
+            segments,
+            vec![segment(0, "Hello there."), segment(2_000, "Bye.")]
+        );
+    }
+
+    #[tokio::test]
+    async fn health_reports_missing_model() {
+        let settings = LocalTranscriptionSettings {
+            model_path: Some("/nonexistent/ggml-base.bin".to_string()),
+            ..LocalTranscriptionSettings::default()
+        };
+        let health = LocalProvider::new(LocalEngineRef::default(), settings)
+            .health()
+            .await;
+        let expected = if cfg!(feature = "local-transcription") {
+            HealthStatus::Misconfigured
+        } else {
+            HealthStatus::Unavailable
+        };
+        assert_eq!(health.status, expected);
+        assert_eq!(health.provider, LOCAL_PROVIDER_ID);
+    }
+
+    #[test]
+    fn missing_model_is_reported() {
+        let wav = wav::write_pcm16(&[0; 1_600], 1, 16_000);
+        let error = LocalEngine::default()
+            .transcribe(&wav, &LocalTranscriptionSettings::default())
+            .unwrap_err();
+        assert_eq!(error, "No local transcription model configured");
+    }
+}
@@ -0,0 +1,215 @@
+//! Level handling for finished recordings: per-device software gain and
+//! loudness normalization followed by a peak limiter.
+
+/// Stored under [`crate::settings::LOUDNESS_SETTINGS_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct LoudnessSettings {
+    pub(crate) normalize: bool,
+    /// Loudness the speech is brought to, as gated RMS in dBFS.
+    pub(crate) target_dbfs: f32,
+    /// Normalization never boosts by more than this, so near-silence is not
+    /// blown up into hiss.
+    pub(crate) max_boost_db: f32,
+    /// Peaks are limited to this level after gain is applied.
+    pub(crate) limiter_ceiling_dbfs: f32,
+}
+
+impl Default for LoudnessSettings {
+    fn default() -> Self {
+        Self {
+            normalize: true,
+            target_dbfs: -20.0,
+            max_boost_db: 24.0,
+            limiter_ceiling_dbfs: -1.0,
+        }
+    }
+}
+
+/// Frames quieter than this never count towards loudness.
+const ABSOLUTE_GATE_DBFS: f32 = -60.0;
+/// Frames this far below the absolute-gated loudness are ignored as pauses.
+const RELATIVE_GATE_DB: f32 = 10.0;
+const LOUDNESS_FRAME_MS: u32 = 50;
+const LIMITER_ATTACK_MS: u32 = 5;
+const LIMITER_RELEASE_MS: u32 = 80;
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn power_to_db(power: f64) -> f32 {
+    (10.0 * power.max(1e-12).log10()) as f32
+}
+
+/// Scales `pcm` by `gain_db`, saturating at full scale.
+pub(crate) fn apply_gain_db(pcm: &mut [i16], gain_db: f32) {
+    if gain_db == 0.0 {
+        return;
+    }
+    let gain = db_to_linear(gain_db);
+    for sample in pcm.iter_mut() {
+        *sample = (f32::from(*sample) * gain)
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+    }
+}
+
+/// Gated RMS loudness of mono `pcm` in dBFS, or `None` when every frame is
+/// below the absolute gate.
+pub(crate) fn gated_loudness_dbfs(pcm: &[i16], sample_rate: u32) -> Option<f32> {
+    let frame_len = (sample_rate * LOUDNESS_FRAME_MS / 1000).max(1) as usize;
+    let full_scale = f64::from(i16::MAX);
+    let frame_powers: Vec<f64> = pcm
+        .chunks(frame_len)
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|&s| (f64::from(s) / full_scale).powi(2))
+                .sum::<f64>()
+                / frame.len() as f64
+        })
+        .collect();
+
+    let mean_power = |gate_db: f32| {
+        let gated: Vec<f64> = frame_powers
+            .iter()
+            .copied()
+            .filter(|&power| power_to_db(power) >= gate_db)
+            .collect();
+        (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+
+    let absolute = mean_power(ABSOLUTE_GATE_DBFS)?;
+    let relative = mean_power(power_to_db(absolute) - RELATIVE_GATE_DB).unwrap_or(absolute);
+    Some(power_to_db(relative))
+}
+
+/// Brings mono `pcm` to the target loudness and limits its peaks. Returns the
+/// gain applied before limiting, in dB.
+pub(crate) fn normalize(pcm: &mut [i16], sample_rate: u32, settings: &LoudnessSettings) -> f32 {
+    let Some(loudness) = gated_loudness_dbfs(pcm, sample_rate) else {
+        return 0.0;
+    };
+    let gain_db = (settings.target_dbfs - loudness).min(settings.max_boost_db);
+
+    let gain = db_to_linear(gain_db);
+    let full_scale = f32::from(i16::MAX);
+    let mut samples: Vec<f32> = pcm
+        .iter()
+        .map(|&s| f32::from(s) / full_scale * gain)
+        .collect();
+    limit(
+        &mut samples,
+        sample_rate,
+        db_to_linear(settings.limiter_ceiling_dbfs),
+    );
+
+    for (out, sample) in pcm.iter_mut().zip(&samples) {
+        *out = (sample.clamp(-1.0, 1.0) * full_scale).round() as i16;
+    }
+    gain_db
+}
+
+/// Look-ahead peak limiter: gain ramps down over the attack time ahead of a
+/// peak and recovers over the release time, so no sample exceeds `ceiling`.
+fn limit(samples: &mut [f32], sample_rate: u32, ceiling: f32) {
+    let ramp = |ms: u32| 1.0 / (sample_rate * ms / 1000).max(1) as f32;
+    let (attack_step, release_step) = (ramp(LIMITER_ATTACK_MS), ramp(LIMITER_RELEASE_MS));
+
+    let mut envelope: Vec<f32> = samples
+        .iter()
+        .map(|s| {
+            let magnitude = s.abs();
+            if magnitude > ceiling {
+                ceiling / magnitude
+            } else {
+                1.0
+            }
+        })
+        .collect();
+    for i in (0..envelope.len().saturating_sub(1)).rev() {
+        envelope[i] = envelope[i].min(envelope[i + 1] + attack_step);
+    }
+    for i in 1..envelope.len() {
+        envelope[i] = envelope[i].min(envelope[i - 1] + release_step);
+    }
+
+    for (sample, gain) in samples.iter_mut().zip(&envelope) {
+        *sample *= gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn tone(amplitude: f64, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / f64::from(SAMPLE_RATE);
+                (amplitude * f64::from(i16::MAX) * (2.0 * std::f64::consts::PI * 300.0 * t).sin())
+                    .round() as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quiet_speech_is_raised_to_target() {
+        // A sine at amplitude 0.01 sits at about -43 dBFS RMS.
+        let mut pcm = tone(0.01, 32_000);
+        let settings = LoudnessSettings::default();
+        let gain_db = normalize(&mut pcm, SAMPLE_RATE, &settings);
+
+        assert!((gain_db - 23.0).abs() < 0.5, "gain {}", gain_db);
+        let loudness = gated_loudness_dbfs(&pcm, SAMPLE_RATE).unwrap();
+        assert!((loudness - settings.target_dbfs).abs() < 0.5);
+    }
+
+    #[test]
+    fn pauses_do_not_lower_measured_loudness() {
+        let speech = tone(0.1, 16_000);
+        let mut with_pause = speech.clone();
+        with_pause.extend(vec![3; 48_000]);
+
+        let speech_only = gated_loudness_dbfs(&speech, SAMPLE_RATE).unwrap();
+        let padded = gated_loudness_dbfs(&with_pause, SAMPLE_RATE).unwrap();
+        assert!((speech_only - padded).abs() < 0.1);
+    }
+
+    #[test]
+    fn limiter_keeps_peaks_under_ceiling() {
+        // A short burst inside quiet speech would clip once the speech is boosted.
+        let mut pcm = tone(0.05, 32_000);
+        pcm.splice(16_000..16_160, tone(0.5, 160));
+        let original = pcm.clone();
+        let settings = LoudnessSettings::default();
+        normalize(&mut pcm, SAMPLE_RATE, &settings);
+
+        let ceiling = db_to_linear(settings.limiter_ceiling_dbfs) * f32::from(i16::MAX);
+        let peak = pcm.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!(f32::from(peak) <= ceiling + 1.0, "peak {}", peak);
+        let burst_gain = f32::from(pcm[16_040]) / f32::from(original[16_040]);
+        let speech_gain = f32::from(pcm[4_010]) / f32::from(original[4_010]);
+        assert!(burst_gain < speech_gain * 0.9, "limiter did not engage");
+    }
+
+    #[test]
+    fn digital_silence_is_left_alone() {
+        let mut pcm = vec![0i16; 16_000];
+        assert_eq!(
+            normalize(&mut pcm, SAMPLE_RATE, &LoudnessSettings::default()),
+            0.0
+        );
+        assert!(pcm.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn device_gain_saturates() {
+        let mut pcm = vec![1000, -1000, 20_000, -20_000];
+        apply_gain_db(&mut pcm, 6.0206);
+        assert_eq!(pcm, vec![2000, -2000, i16::MAX, i16::MIN]);
+    }
+}
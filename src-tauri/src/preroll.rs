@@ -0,0 +1,196 @@
+use crate::audio;
+use crate::capture_format::SessionCapture;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+const MAX_PREROLL_MS: u64 = 1000;
+
+/// Optional "warm microphone": keeps an input stream open between sessions so
+/// the moments before a shortcut press can be prepended to the recording.
+/// Stored under [`crate::settings::WARM_MICROPHONE_KEY`]; off by default.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct WarmMicrophoneSettings {
+    pub(crate) enabled: bool,
+    pub(crate) preroll_ms: u64,
+}
+
+impl Default for WarmMicrophoneSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preroll_ms: 500,
+        }
+    }
+}
+
+/// An always-open input stream holding the most recent `preroll_ms` of audio.
+/// The stream lives on its own thread and closes when this value is dropped.
+pub(crate) struct WarmMicrophone {
+    history: Arc<Mutex<VecDeque<i16>>>,
+    device_name: String,
+    sample_rate: u32,
+    channels: u16,
+    input_channel: Option<u16>,
+    _stop: mpsc::Sender<()>,
+}
+
+impl WarmMicrophone {
+    /// Opens `device` in the format sessions on it capture with, so the
+    /// pre-roll can be spliced in front of their recordings.
+    pub(crate) fn start(
+        device: cpal::Device,
+        capture: SessionCapture,
+        preroll_ms: u64,
+    ) -> Result<Self, String> {
+        let device_name = device.name().unwrap_or_default();
+        let captured_channels = capture.captured_channels();
+        let SessionCapture {
+            config,
+            buffer_frames,
+            input_channel,
+        } = capture;
+        let sample_format = config.sample_format();
+        let mut stream_config: cpal::StreamConfig = config.into();
+        if let Some(frames) = buffer_frames {
+            stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+        }
+        let sample_rate = stream_config.sample_rate.0;
+        let channels = stream_config.channels;
+        let stream_channels = usize::from(channels.max(1));
+
+        let capacity = audio::capture_buffer_samples(
+            sample_rate,
+            captured_channels,
+            preroll_ms.min(MAX_PREROLL_MS),
+        );
+        // Whole frames only, so the history always starts on channel 0.
+        let capacity = capacity - capacity % usize::from(captured_channels.max(1));
+        let history = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+        let history_callback = history.clone();
+
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let (ready_sender, ready_receiver) = mpsc::channel::<Result<(), String>>();
+
+        thread::spawn(move || {
+            let (error_sender, _stream_errors) = mpsc::channel();
+            let stream = audio::build_input_stream(
+                &device,
+                &stream_config,
+                sample_format,
+                error_sender,
+                move |data: &[i16]| {
+                    // Never wait in the audio callback; a contended update is skipped.
+                    let Ok(mut history) = history_callback.try_lock() else {
+                        return;
+                    };
+                    match input_channel {
+                        Some(channel) => {
+                            let frames = data.len() / stream_channels;
+                            let excess = (history.len() + frames).saturating_sub(capacity);
+                            let excess = excess.min(history.len());
+                            history.drain(..excess);
+                            let selected = data
+                                .iter()
+                                .skip(usize::from(channel))
+                                .step_by(stream_channels)
+                                .copied();
+                            history.extend(selected.skip(frames.saturating_sub(capacity)));
+                        }
+                        None => {
+                            let data = &data[data.len().saturating_sub(capacity)..];
+                            let excess = (history.len() + data.len()).saturating_sub(capacity);
+                            history.drain(..excess);
+                            history.extend(data.iter().copied());
+                        }
+                    }
+                },
+            );
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            if let Err(e) = stream.play() {
+                let _ = ready_sender.send(Err(format!("Could not start stream: {}", e)));
+                return;
+            }
+            let _ = ready_sender.send(Ok(()));
+
+            // Returns once the owning WarmMicrophone is dropped.
+            let _ = stop_receiver.recv();
+            drop(stream);
+            println!("Warm microphone stream closed.");
+        });
+
+        ready_receiver
+            .recv()
+            .map_err(|_| "Warm microphone thread exited unexpectedly".to_string())??;
+        println!(
+            "Warm microphone open on '{}' ({} ms pre-roll).",
+            device_name,
+            preroll_ms.min(MAX_PREROLL_MS)
+        );
+
+        Ok(Self {
+            history,
+            device_name,
+            sample_rate,
+            channels,
+            input_channel,
+            _stop: stop_sender,
+        })
+    }
+
+    /// Whether the pre-roll can be spliced in front of a session recording
+    /// from `device_name` in `stream_config` on `input_channel`.
+    pub(crate) fn matches(
+        &self,
+        device_name: &str,
+        stream_config: &cpal::StreamConfig,
+        input_channel: Option<u16>,
+    ) -> bool {
+        self.device_name == device_name
+            && self.sample_rate == stream_config.sample_rate.0
+            && self.channels == stream_config.channels
+            && self.input_channel == input_channel
+    }
+
+    /// Pushes the pre-roll into a session's capture ring, leaving out the
+    /// newest `overlap_samples` that the session stream captured itself.
+    /// `muted` maps the pre-roll length to the samples to write as silence.
+    /// Returns the pre-roll length and the number of samples that did not fit.
+    pub(crate) fn write_preroll(
+        &self,
+        producer: &mut rtrb::Producer<i16>,
+        overlap_samples: usize,
+        muted: impl FnOnce(usize) -> Range<usize>,
+    ) -> (usize, usize) {
+        let Ok(history) = self.history.lock() else {
+            return (0, 0);
+        };
+        let keep = history.len().saturating_sub(overlap_samples);
+        let muted = muted(keep);
+        let samples =
+            history.iter().take(keep).enumerate().map(
+                |(i, &sample)| {
+                    if muted.contains(&i) {
+                        0
+                    } else {
+                        sample
+                    }
+                },
+            );
+
+        let written = match producer.write_chunk_uninit(keep.min(producer.slots())) {
+            Ok(chunk) => chunk.fill_from_iter(samples),
+            Err(_) => 0,
+        };
+        (keep, keep - written)
+    }
+}
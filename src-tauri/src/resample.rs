@@ -0,0 +1,290 @@
+use std::f32::consts::PI;
+
+pub(crate) const DEFAULT_OUTPUT_SAMPLE_RATE: u32 = 16_000;
+
+/// Half-width of the windowed-sinc kernel, in input samples at unity ratio.
+const SINC_HALF_TAPS: usize = 16;
+
+/// Averages interleaved frames down to a single channel.
+pub(crate) fn downmix_to_mono(samples: &[i16], channels: u16) -> Vec<i16> {
+    let channels = usize::from(channels.max(1));
+    if channels == 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks_exact(channels)
+        .map(|frame| {
+            let sum: i32 = frame.iter().map(|&s| i32::from(s)).sum();
+            (sum / channels as i32) as i16
+        })
+        .collect()
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn blackman(position: f32, half_width: f32) -> f32 {
+    // `position` runs from -half_width to +half_width.
+    let n = (position + half_width) / (2.0 * half_width);
+    0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos()
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Most kernel phases precomputed. Rate pairs needing more round each output
+/// to the nearest of this many fractional positions.
+const MAX_PHASES: u64 = 1024;
+
+/// Band-limited resampler for mono PCM with a Blackman-windowed sinc kernel.
+/// When downsampling, the kernel's cutoff is lowered to the target Nyquist
+/// frequency so speech energy above it does not alias back in.
+///
+/// The kernel is precomputed for every fractional position an output sample
+/// can fall on (a polyphase table), and input arrives in any number of
+/// [`Resampler::process`] calls: the output is the same as resampling the
+/// whole input at once, with no seams where the pieces meet.
+pub(crate) struct Resampler {
+    /// Output advances `step_num / step_den` input samples per sample.
+    step_num: u64,
+    step_den: u64,
+    phases: u64,
+    /// Kernel weights, `taps` per phase, normalized to unity gain.
+    table: Vec<f32>,
+    /// Input samples before and after an output's position that it reads.
+    half_taps: i64,
+    /// Input not yet fully used, starting at absolute index `history_start`.
+    history: Vec<i16>,
+    history_start: u64,
+    received: u64,
+    produced: u64,
+}
+
+impl Resampler {
+    pub(crate) fn new(from_rate: u32, to_rate: u32) -> Self {
+        let (from_rate, to_rate) = (u64::from(from_rate.max(1)), u64::from(to_rate.max(1)));
+        let divisor = gcd(from_rate, to_rate);
+        let (step_num, step_den) = (from_rate / divisor, to_rate / divisor);
+
+        let cutoff = (to_rate as f32 / from_rate as f32).min(1.0);
+        let half_width = SINC_HALF_TAPS as f32 / cutoff;
+        let half_taps = half_width.ceil() as i64;
+        let phases = if from_rate == to_rate {
+            1
+        } else {
+            step_den.min(MAX_PHASES)
+        };
+
+        let taps = 2 * half_taps as usize;
+        let mut table = Vec::with_capacity(phases as usize * taps);
+        for phase in 0..phases {
+            let frac = phase as f32 / phases as f32;
+            let start = table.len();
+            // Tap `j` reads the input `j` samples after the output's base index.
+            for j in (1 - half_taps)..=half_taps {
+                let offset = frac - j as f32;
+                let weight = if offset.abs() <= half_width {
+                    cutoff * sinc(offset * cutoff) * blackman(offset, half_width)
+                } else {
+                    0.0
+                };
+                table.push(weight);
+            }
+            let sum: f32 = table[start..].iter().sum();
+            if sum.abs() > f32::EPSILON {
+                table[start..].iter_mut().for_each(|weight| *weight /= sum);
+            }
+        }
+
+        Self {
+            step_num,
+            step_den,
+            phases,
+            table,
+            half_taps,
+            history: Vec::new(),
+            history_start: 0,
+            received: 0,
+            produced: 0,
+        }
+    }
+
+    /// Base input index and kernel phase of output sample `index`.
+    fn position(&self, index: u64) -> (i64, usize) {
+        let exact = index * self.step_num;
+        let mut base = exact / self.step_den;
+        let remainder = exact % self.step_den;
+        let mut phase = if self.phases == self.step_den {
+            remainder
+        } else {
+            (remainder * self.phases + self.step_den / 2) / self.step_den
+        };
+        if phase == self.phases {
+            base += 1;
+            phase = 0;
+        }
+        (base as i64, phase as usize)
+    }
+
+    /// Computes output sample `index`. Inputs past either end repeat the
+    /// first or last sample.
+    fn output(&self, index: u64) -> i16 {
+        let (base, phase) = self.position(index);
+        let taps = 2 * self.half_taps as usize;
+        let weights = &self.table[phase * taps..(phase + 1) * taps];
+        let first = self.history_start as i64;
+        let last = first + self.history.len() as i64 - 1;
+        let mut acc = 0.0f32;
+        for (tap, weight) in weights.iter().enumerate() {
+            let k = (base + 1 - self.half_taps + tap as i64).clamp(first, last);
+            acc += f32::from(self.history[(k - first) as usize]) * weight;
+        }
+        acc.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+    }
+
+    /// Adds input and returns every output sample whose kernel it completes.
+    pub(crate) fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        self.history.extend_from_slice(input);
+        self.received += input.len() as u64;
+        let mut output = Vec::new();
+        while !self.history.is_empty() {
+            let (base, _) = self.position(self.produced);
+            if base + self.half_taps >= self.received as i64 {
+                break;
+            }
+            output.push(self.output(self.produced));
+            self.produced += 1;
+        }
+
+        // Keep what the next output still reads; earlier input only matters
+        // as the sample repeated before the start, which no output reads
+        // once the kernel has moved past it.
+        let (next_base, _) = self.position(self.produced);
+        let needed_from = (next_base + 1 - self.half_taps).max(0) as u64;
+        if needed_from > self.history_start {
+            let drop = (needed_from - self.history_start).min(self.history.len() as u64);
+            self.history.drain(..drop as usize);
+            self.history_start += drop;
+        }
+        output
+    }
+
+    /// Returns the outputs still owed once the input has ended, for a total
+    /// of the input length times the rate ratio, rounded.
+    pub(crate) fn finish(mut self) -> Vec<i16> {
+        if self.history.is_empty() {
+            return Vec::new();
+        }
+        let total = (self.received * self.step_den * 2 + self.step_num) / (2 * self.step_num);
+        let mut output = Vec::new();
+        while self.produced < total {
+            output.push(self.output(self.produced));
+            self.produced += 1;
+        }
+        output
+    }
+}
+
+/// Resamples a whole mono recording; see [`Resampler`].
+pub(crate) fn resample_mono(samples: &[i16], from_rate: u32, to_rate: u32) -> Vec<i16> {
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let mut resampler = Resampler::new(from_rate, to_rate);
+    let mut output = resampler.process(samples);
+    output.extend(resampler.finish());
+    output
+}
+
+/// Converts captured interleaved PCM to mono at `to_rate`.
+pub(crate) fn convert_for_speech(
+    samples: &[i16],
+    native_rate: u32,
+    native_channels: u16,
+    to_rate: u32,
+) -> Vec<i16> {
+    let mono = downmix_to_mono(samples, native_channels);
+    resample_mono(&mono, native_rate, to_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, amplitude: f32, rate: u32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                (amplitude * (2.0 * PI * hz * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[i16]) -> f32 {
+        let sum: f64 = samples.iter().map(|&s| f64::from(s) * f64::from(s)).sum();
+        (sum / samples.len().max(1) as f64).sqrt() as f32
+    }
+
+    #[test]
+    fn output_length_follows_the_rate_ratio() {
+        assert_eq!(resample_mono(&vec![0; 4_800], 48_000, 16_000).len(), 1_600);
+        assert_eq!(
+            resample_mono(&vec![0; 44_100], 44_100, 16_000).len(),
+            16_000
+        );
+        assert_eq!(resample_mono(&vec![0; 1_000], 8_000, 16_000).len(), 2_000);
+        assert_eq!(resample_mono(&vec![0; 1_001], 44_100, 16_000).len(), 363);
+    }
+
+    #[test]
+    fn dc_passes_at_unity_gain() {
+        for (from, to) in [(48_000, 16_000), (44_100, 16_000), (16_000, 48_000)] {
+            let output = resample_mono(&vec![10_000; 9_000], from, to);
+            assert!(
+                output.iter().all(|&s| (s - 10_000).abs() <= 1),
+                "{} -> {}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn speech_band_passes_and_content_above_new_nyquist_is_rejected() {
+        let passband = resample_mono(&sine(1_000.0, 10_000.0, 48_000, 48_000), 48_000, 16_000);
+        let gain = rms(&passband[500..15_500]) / rms(&sine(1_000.0, 10_000.0, 16_000, 16_000));
+        assert!((0.98..=1.02).contains(&gain), "passband gain {}", gain);
+
+        // 12 kHz is above the 8 kHz Nyquist frequency of 16 kHz audio and
+        // would alias to 4 kHz without filtering.
+        let stopband = resample_mono(&sine(12_000.0, 10_000.0, 48_000, 48_000), 48_000, 16_000);
+        let level = rms(&stopband[500..15_500]) / (10_000.0 / 2f32.sqrt());
+        assert!(level < 0.01, "stopband level {}", level);
+    }
+
+    #[test]
+    fn chunked_input_matches_whole_input() {
+        let input = sine(440.0, 8_000.0, 44_100, 20_000);
+        let whole = resample_mono(&input, 44_100, 16_000);
+
+        let mut resampler = Resampler::new(44_100, 16_000);
+        let mut chunked = Vec::new();
+        for chunk in input.chunks(1_234) {
+            chunked.extend(resampler.process(chunk));
+        }
+        chunked.extend(resampler.finish());
+        assert_eq!(chunked, whole);
+    }
+}
@@ -0,0 +1,212 @@
+//! Conversion of every cpal sample format to signed 16-bit PCM.
+//!
+//! Integer formats keep their most significant 16 bits; unsigned formats are
+//! re-centred on their origin first (`1 << (bits - 1)`). Float formats are
+//! clamped to `[-1.0, 1.0]` and scaled by `i16::MAX`.
+
+pub(crate) trait ToPcm16: Copy {
+    fn to_pcm16(self) -> i16;
+}
+
+impl ToPcm16 for i8 {
+    fn to_pcm16(self) -> i16 {
+        i16::from(self) << 8
+    }
+}
+
+impl ToPcm16 for i16 {
+    fn to_pcm16(self) -> i16 {
+        self
+    }
+}
+
+impl ToPcm16 for i32 {
+    fn to_pcm16(self) -> i16 {
+        (self >> 16) as i16
+    }
+}
+
+impl ToPcm16 for i64 {
+    fn to_pcm16(self) -> i16 {
+        (self >> 48) as i16
+    }
+}
+
+impl ToPcm16 for u8 {
+    fn to_pcm16(self) -> i16 {
+        i16::from((self ^ 0x80) as i8) << 8
+    }
+}
+
+impl ToPcm16 for u16 {
+    fn to_pcm16(self) -> i16 {
+        (self ^ 0x8000) as i16
+    }
+}
+
+impl ToPcm16 for u32 {
+    fn to_pcm16(self) -> i16 {
+        ((self ^ 0x8000_0000) >> 16) as u16 as i16
+    }
+}
+
+impl ToPcm16 for u64 {
+    fn to_pcm16(self) -> i16 {
+        ((self ^ 0x8000_0000_0000_0000) >> 48) as u16 as i16
+    }
+}
+
+impl ToPcm16 for f32 {
+    fn to_pcm16(self) -> i16 {
+        if self.is_nan() {
+            return 0;
+        }
+        (self.clamp(-1.0, 1.0) * f32::from(i16::MAX)).round() as i16
+    }
+}
+
+impl ToPcm16 for f64 {
+    fn to_pcm16(self) -> i16 {
+        if self.is_nan() {
+            return 0;
+        }
+        (self.clamp(-1.0, 1.0) * f64::from(i16::MAX)).round() as i16
+    }
+}
+
+/// Appends `input` converted to 16-bit PCM to `output`.
+pub(crate) fn extend_pcm16<T: ToPcm16>(output: &mut Vec<i16>, input: &[T]) {
+    output.extend(input.iter().map(|&s| s.to_pcm16()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINE_LEN: usize = 480;
+
+    fn sine(amplitude: f64) -> Vec<f64> {
+        (0..SINE_LEN)
+            .map(|i| amplitude * (2.0 * std::f64::consts::PI * i as f64 / SINE_LEN as f64).sin())
+            .collect()
+    }
+
+    fn reference_pcm16(wave: &[f64]) -> Vec<i16> {
+        wave.iter()
+            .map(|&s| (s * f64::from(i16::MAX)).round() as i16)
+            .collect()
+    }
+
+    /// Largest per-sample error of `converted` against `reference`.
+    fn max_error(converted: &[i16], reference: &[i16]) -> i32 {
+        converted
+            .iter()
+            .zip(reference)
+            .map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn convert<T: ToPcm16>(input: &[T]) -> Vec<i16> {
+        let mut output = Vec::new();
+        extend_pcm16(&mut output, input);
+        output
+    }
+
+    #[test]
+    fn signed_integer_sines_match_reference() {
+        let wave = sine(0.8);
+        let reference = reference_pcm16(&wave);
+
+        let i8_wave: Vec<i8> = wave.iter().map(|&s| (s * 127.0).round() as i8).collect();
+        let i16_wave: Vec<i16> = reference.clone();
+        let i32_wave: Vec<i32> = wave
+            .iter()
+            .map(|&s| (s * f64::from(i32::MAX)).round() as i32)
+            .collect();
+        let i64_wave: Vec<i64> = wave.iter().map(|&s| (s * i64::MAX as f64) as i64).collect();
+
+        assert!(max_error(&convert(&i8_wave), &reference) <= 256 + 128);
+        assert_eq!(convert(&i16_wave), reference);
+        assert!(max_error(&convert(&i32_wave), &reference) <= 2);
+        assert!(max_error(&convert(&i64_wave), &reference) <= 2);
+    }
+
+    #[test]
+    fn unsigned_integer_sines_match_reference() {
+        let wave = sine(0.8);
+        let reference = reference_pcm16(&wave);
+
+        let u8_wave: Vec<u8> = wave
+            .iter()
+            .map(|&s| (128.0 + s * 127.0).round() as u8)
+            .collect();
+        let u16_wave: Vec<u16> = wave
+            .iter()
+            .map(|&s| (32768.0 + s * 32767.0).round() as u16)
+            .collect();
+        let u32_wave: Vec<u32> = wave
+            .iter()
+            .map(|&s| (2147483648.0 + s * 2147483647.0).round() as u32)
+            .collect();
+        let u64_wave: Vec<u64> = wave
+            .iter()
+            .map(|&s| (9223372036854775808.0 + s * 9223372036854775807.0) as u64)
+            .collect();
+
+        assert!(max_error(&convert(&u8_wave), &reference) <= 256 + 128);
+        assert!(max_error(&convert(&u16_wave), &reference) <= 1);
+        assert!(max_error(&convert(&u32_wave), &reference) <= 2);
+        assert!(max_error(&convert(&u64_wave), &reference) <= 2);
+    }
+
+    #[test]
+    fn float_sines_match_reference() {
+        let wave = sine(0.8);
+        let reference = reference_pcm16(&wave);
+
+        let f32_wave: Vec<f32> = wave.iter().map(|&s| s as f32).collect();
+
+        assert!(max_error(&convert(&f32_wave), &reference) <= 1);
+        assert_eq!(convert(&wave), reference);
+    }
+
+    #[test]
+    fn u16_keeps_negative_half_of_waveform() {
+        assert_eq!(0u16.to_pcm16(), i16::MIN);
+        assert_eq!(16384u16.to_pcm16(), -16384);
+        assert_eq!(32768u16.to_pcm16(), 0);
+        assert_eq!(u16::MAX.to_pcm16(), i16::MAX);
+
+        let negative = sine(0.5)
+            .iter()
+            .map(|&s| (32768.0 + s * 32767.0).round() as u16)
+            .filter(|&s| s.to_pcm16() < 0)
+            .count();
+        assert!(negative > SINE_LEN / 3);
+    }
+
+    #[test]
+    fn format_extremes_map_to_pcm16_extremes() {
+        assert_eq!(i8::MIN.to_pcm16(), i16::MIN);
+        assert_eq!(i32::MIN.to_pcm16(), i16::MIN);
+        assert_eq!(i32::MAX.to_pcm16(), i16::MAX);
+        assert_eq!(i64::MIN.to_pcm16(), i16::MIN);
+        assert_eq!(i64::MAX.to_pcm16(), i16::MAX);
+        assert_eq!(0u8.to_pcm16(), i16::MIN);
+        assert_eq!(128u8.to_pcm16(), 0);
+        assert_eq!(0u32.to_pcm16(), i16::MIN);
+        assert_eq!(u32::MAX.to_pcm16(), i16::MAX);
+        assert_eq!(0u64.to_pcm16(), i16::MIN);
+        assert_eq!(u64::MAX.to_pcm16(), i16::MAX);
+    }
+
+    #[test]
+    fn floats_are_clamped_and_nan_is_silent() {
+        assert_eq!(1.5f32.to_pcm16(), i16::MAX);
+        assert_eq!((-1.5f32).to_pcm16(), -i16::MAX);
+        assert_eq!(f32::NAN.to_pcm16(), 0);
+        assert_eq!(2.0f64.to_pcm16(), i16::MAX);
+        assert_eq!(f64::NAN.to_pcm16(), 0);
+    }
+}
@@ -0,0 +1,981 @@
+//! One recording from shortcut press to delivered audio. Both recording
+//! shortcuts run the same stages: capture on a dedicated thread, process the
+//! finished capture, then deliver it according to the session's output mode.
+
+use crate::audio::{AudioLevel, Capture, StopSignal};
+use crate::cue::{CueGate, CueTiming, StartCueSettings};
+use crate::diagnostics::{self, RecordingDiagnostics};
+use crate::dsp::{DspChain, DspSettings};
+use crate::loudness::{self, LoudnessSettings};
+use crate::resample;
+use crate::source::AudioSource;
+use crate::state::{AppStateRef, RecorderState};
+use crate::streaming_transcription::{self, PartialTranscript};
+use crate::transcription::{ProviderFuture, TranscriptionProvider};
+use crate::vad::{self, VadSettings};
+use crate::wav::WavPcm;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Recordings this short or shorter are dropped rather than transcribed. The
+/// whole capture counts, so a short word in a longer press is kept however
+/// much silence is trimmed around it.
+const MIN_TRANSCRIBE_SECS: f64 = 1.0;
+
+/// How long a finished capture waits for the streaming provider to answer the
+/// final chunk before processing goes ahead without it.
+const STREAM_FINISH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Sends a session's audio to its streaming provider; resolves to the last
+/// partial transcript.
+type StreamTask = tokio::task::JoinHandle<Result<PartialTranscript, String>>;
+
+/// What happens to a session's audio once it is processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputMode {
+    /// Transcribed and sent to the chat window, which is brought to the front.
+    Chat,
+    /// Transcribed and pasted into the focused application.
+    Clipboard,
+}
+
+/// Format the device captures in (`native_*`) and the format of the audio
+/// delivered for transcription (`output_*`).
+#[derive(Debug, Clone)]
+pub(crate) struct AudioFormat {
+    pub(crate) native_sample_rate: u32,
+    pub(crate) native_channels: u16,
+    pub(crate) output_sample_rate: u32,
+    pub(crate) output_channels: u16,
+}
+
+/// Settings a session reads once when it starts, so changes made while it
+/// runs apply from the next session.
+#[derive(Debug, Clone)]
+pub(crate) struct SessionConfig {
+    pub(crate) output_mode: OutputMode,
+    pub(crate) format: AudioFormat,
+    /// Software gain for the capture device, in dB.
+    pub(crate) input_gain_db: f32,
+    pub(crate) start_cue: StartCueSettings,
+    pub(crate) dsp: DspSettings,
+    pub(crate) vad: VadSettings,
+    pub(crate) loudness: LoudnessSettings,
+}
+
+/// Processed audio handed to the output.
+pub(crate) struct Delivery {
+    pub(crate) session_id: u64,
+    pub(crate) output_mode: OutputMode,
+    /// PCM in the session's output format. Whoever sends it on encodes it,
+    /// in pieces when it is too long for one request.
+    pub(crate) audio: WavPcm,
+    pub(crate) diagnostics: RecordingDiagnostics,
+}
+
+/// How a session reports progress. The app turns these into Tauri events.
+pub(crate) trait SessionEvents: Send + Sync + 'static {
+    fn state_changed(&self, state: RecorderState);
+    fn level(&self, level: AudioLevel);
+    fn capture_failed(&self, source: &str, error: &str);
+    fn overflow(&self, dropped_samples: u64, capacity_samples: usize);
+    fn diagnostics(&self, diagnostics: &RecordingDiagnostics);
+    /// Voice activity detection found nothing to transcribe.
+    fn no_speech(&self, session_id: u64);
+    /// A newer transcript of the audio streamed so far.
+    fn partial_transcript(&self, session_id: u64, partial: &PartialTranscript);
+    /// Plays the record-end cue for sessions that end without a transcript.
+    fn end_cue(&self);
+    /// Resolves once the output is done with the audio, e.g. after the
+    /// transcript was pasted; the recorder stays `Transcribing` until then.
+    fn deliver(&self, delivery: Delivery) -> ProviderFuture<'_, Result<(), String>>;
+}
+
+/// Result of the process stage.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outcome {
+    /// Diagnostics found nothing worth transcribing.
+    Unusable,
+    /// Nothing was left after processing.
+    Empty,
+    /// Voice activity detection found no speech.
+    NoSpeech,
+    /// Not longer than [`MIN_TRANSCRIBE_SECS`].
+    TooShort,
+    /// Audio ready for speech-to-text.
+    Ready(WavPcm),
+}
+
+pub(crate) struct Processed {
+    pub(crate) diagnostics: RecordingDiagnostics,
+    pub(crate) outcome: Outcome,
+}
+
+pub(crate) struct RecordingSession {
+    id: u64,
+    config: SessionConfig,
+    start_cue: CueTiming,
+    stop: StopSignal,
+    captured_from: Arc<OnceLock<Instant>>,
+    /// Provider that hears the audio while it is captured, and the chunk size.
+    streaming: Option<(Arc<dyn TranscriptionProvider>, Duration)>,
+}
+
+impl RecordingSession {
+    /// `start_cue` is the timing of the record-start sound played for this
+    /// session, used to keep the sound out of the recording. `stop` ends the
+    /// capture stage; callers create it before any setup so a stop that
+    /// arrives while the device is being opened is not lost.
+    pub(crate) fn new(config: SessionConfig, start_cue: CueTiming, stop: StopSignal) -> Self {
+        Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            config,
+            start_cue,
+            stop,
+            captured_from: Arc::new(OnceLock::new()),
+            streaming: None,
+        }
+    }
+
+    /// Streams the audio to `provider` in chunks of about `chunk` while it is
+    /// captured, reporting partial transcripts as they come back.
+    pub(crate) fn stream_to(
+        mut self,
+        provider: Arc<dyn TranscriptionProvider>,
+        chunk: Duration,
+    ) -> Self {
+        self.streaming = Some((provider, chunk));
+        self
+    }
+
+    /// Filled in by the source with when its first sample was captured.
+    pub(crate) fn captured_from(&self) -> Arc<OnceLock<Instant>> {
+        self.captured_from.clone()
+    }
+
+    /// Holds capture back while the start cue plays, if the settings ask for it.
+    pub(crate) fn cue_gate(&self) -> Option<CueGate> {
+        self.config.start_cue.gate(&self.start_cue)
+    }
+
+    /// Runs every stage and leaves the recorder `Idle`.
+    pub(crate) async fn run(
+        self,
+        source: Box<dyn AudioSource>,
+        events: Arc<dyn SessionEvents>,
+        app_state: AppStateRef,
+    ) {
+        println!(
+            "[session {}] Started ({:?}) on {}.",
+            self.id,
+            self.config.output_mode,
+            source.name()
+        );
+        if let Some(capture) = self.capture(source, events.clone()).await {
+            println!(
+                "[session {}] Collected {} samples.",
+                self.id,
+                capture.samples.len()
+            );
+            events.overflow(capture.dropped_samples, capture.capacity_samples);
+            if let Some(processed) = self.process(capture).await {
+                events.diagnostics(&processed.diagnostics);
+                self.deliver(processed, events.as_ref(), &app_state).await;
+            }
+        }
+
+        set_state(&app_state, events.as_ref(), RecorderState::Idle).await;
+        println!("[session {}] Finished.", self.id);
+    }
+
+    /// Capture stage: records on a dedicated thread and resolves once the
+    /// source has flushed its last buffer and any stream has finished.
+    async fn capture(
+        &self,
+        source: Box<dyn AudioSource>,
+        events: Arc<dyn SessionEvents>,
+    ) -> Option<Capture> {
+        if self.stop.is_stopped() {
+            println!(
+                "[session {}] Stopped before capture began; nothing recorded.",
+                self.id
+            );
+            return None;
+        }
+        let (capture_done, capture_finished) = tokio::sync::oneshot::channel();
+        let stop = self.stop.clone();
+        let id = self.id;
+        let (audio_tx, streaming) = match self.start_stream(events.clone()) {
+            Some((audio_tx, streaming)) => (Some(audio_tx), Some(streaming)),
+            None => (None, None),
+        };
+        thread::spawn(move || {
+            let source_name = source.name();
+            let level_events = events.clone();
+            let mut on_audio = |samples: &[i16]| {
+                if let Some(audio_tx) = &audio_tx {
+                    // Fails once the stream has given up, which only ends partials.
+                    let _ = audio_tx.send(samples.to_vec());
+                }
+            };
+            let capture = source
+                .capture(stop, &mut |level| level_events.level(level), &mut on_audio)
+                .unwrap_or_else(|err| Capture {
+                    error: Some(err),
+                    ..Capture::default()
+                });
+            if let Some(err) = &capture.error {
+                eprintln!(
+                    "[session {}] Recording error on '{}': {}",
+                    id, source_name, err
+                );
+                events.capture_failed(&source_name, err);
+            }
+            let _ = capture_done.send(capture);
+        });
+
+        let capture = capture_finished.await;
+        if let Some(streaming) = streaming {
+            self.finish_stream(streaming).await;
+        }
+        match capture {
+            Ok(capture) => Some(capture),
+            Err(_) => {
+                eprintln!(
+                    "[session {}] Capture thread ended without handing over its audio.",
+                    self.id
+                );
+                None
+            }
+        }
+    }
+
+    /// Starts streaming captured audio when the session has a streaming
+    /// provider. Returns the sender the capture thread feeds and the task
+    /// that sends it on.
+    fn start_stream(
+        &self,
+        events: Arc<dyn SessionEvents>,
+    ) -> Option<(tokio::sync::mpsc::UnboundedSender<Vec<i16>>, StreamTask)> {
+        let (provider, chunk) = self.streaming.clone()?;
+        let (audio_tx, audio_rx) = tokio::sync::mpsc::unbounded_channel();
+        let id = self.id;
+        let streaming = tokio::spawn(streaming_transcription::stream_partials(
+            provider,
+            streaming_transcription::stream_id(id),
+            self.config.format.clone(),
+            chunk,
+            audio_rx,
+            move |partial: &PartialTranscript| events.partial_transcript(id, partial),
+        ));
+        Some((audio_tx, streaming))
+    }
+
+    /// Waits for the final partial transcript, so none arrive after the
+    /// session has moved on. Streaming only adds partials; failures are logged
+    /// and the recording is transcribed as usual.
+    async fn finish_stream(&self, mut streaming: StreamTask) {
+        match tokio::time::timeout(STREAM_FINISH_TIMEOUT, &mut streaming).await {
+            Ok(Ok(Ok(partial))) => println!(
+                "[session {}] Stream finished with {} stable characters.",
+                self.id,
+                partial.stable.chars().count()
+            ),
+            Ok(Ok(Err(e))) => eprintln!("[session {}] Streaming failed: {}", self.id, e),
+            Ok(Err(e)) => eprintln!("[session {}] Streaming task failed: {}", self.id, e),
+            Err(_) => {
+                streaming.abort();
+                eprintln!(
+                    "[session {}] Gave up waiting for the final partial transcript.",
+                    self.id
+                );
+            }
+        }
+    }
+
+    /// Process stage. Runs [`process_capture`] on a blocking thread, as
+    /// resampling and DSP on a long recording would stall the async runtime.
+    async fn process(&self, capture: Capture) -> Option<Processed> {
+        let id = self.id;
+        let config = self.config.clone();
+        let start_cue = self.start_cue.clone();
+        let captured_from = self.captured_from.get().copied();
+        let processing = tokio::task::spawn_blocking(move || {
+            process_capture(id, &config, &start_cue, captured_from, capture)
+        });
+        match processing.await {
+            Ok(processed) => Some(processed),
+            Err(e) => {
+                eprintln!("[session {}] Processing failed: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// Deliver stage: hands transcribable audio to the output, or plays the
+    /// end cue for sessions that produced none.
+    async fn deliver(
+        &self,
+        processed: Processed,
+        events: &dyn SessionEvents,
+        app_state: &AppStateRef,
+    ) {
+        match processed.outcome {
+            Outcome::Ready(audio) => {
+                set_state(app_state, events, RecorderState::Transcribing).await;
+                println!(
+                    "[session {}] Delivering {} samples of {} Hz audio ({:?}).",
+                    self.id,
+                    audio.samples.len(),
+                    audio.sample_rate,
+                    self.config.output_mode
+                );
+                let delivery = Delivery {
+                    session_id: self.id,
+                    output_mode: self.config.output_mode,
+                    audio,
+                    diagnostics: processed.diagnostics,
+                };
+                if let Err(e) = events.deliver(delivery).await {
+                    eprintln!("[session {}] Failed to deliver audio: {}", self.id, e);
+                }
+            }
+            Outcome::NoSpeech => {
+                events.no_speech(self.id);
+                events.end_cue();
+            }
+            Outcome::Unusable | Outcome::TooShort => events.end_cue(),
+            Outcome::Empty => {}
+        }
+    }
+}
+
+/// Diagnostics on the raw capture with the input gain counted towards its
+/// level, then conversion to the output format, gain, cue removal, DSP, voice
+/// activity detection and loudness normalization.
+fn process_capture(
+    id: u64,
+    config: &SessionConfig,
+    start_cue: &CueTiming,
+    captured_from: Option<Instant>,
+    capture: Capture,
+) -> Processed {
+    let format = &config.format;
+    let diagnostics = diagnostics::analyze(
+        &capture.samples,
+        format.native_sample_rate,
+        format.native_channels,
+        config.input_gain_db,
+    );
+    let processed = |outcome| Processed {
+        diagnostics: diagnostics.clone(),
+        outcome,
+    };
+    if !diagnostics.usable {
+        println!(
+            "[session {}] Recording unusable ({:?}). Skipping transcription.",
+            id, diagnostics.issues
+        );
+        return processed(Outcome::Unusable);
+    }
+
+    let sample_rate = format.output_sample_rate;
+    let mut pcm = resample::convert_for_speech(
+        &capture.samples,
+        format.native_sample_rate,
+        format.native_channels,
+        sample_rate,
+    );
+    println!(
+        "[session {}] Converted {} Hz/{} ch capture to {} Hz/{} ch ({} samples).",
+        id,
+        format.native_sample_rate,
+        format.native_channels,
+        sample_rate,
+        format.output_channels,
+        pcm.len()
+    );
+
+    if config.input_gain_db != 0.0 {
+        loudness::apply_gain_db(&mut pcm, config.input_gain_db);
+        println!(
+            "[session {}] Applied {:+.1} dB device input gain.",
+            id, config.input_gain_db
+        );
+    }
+
+    let cue_samples = config
+        .start_cue
+        .trim(start_cue, captured_from, &mut pcm, sample_rate);
+    if cue_samples > 0 {
+        println!(
+            "[session {}] Silenced {} samples of the start cue.",
+            id, cue_samples
+        );
+    }
+
+    let mut dsp_chain = DspChain::from_settings(&config.dsp, sample_rate);
+    if !dsp_chain.is_empty() {
+        dsp_chain.process_pcm16(&mut pcm);
+        println!(
+            "[session {}] Applied DSP chain {:?}.",
+            id,
+            dsp_chain.stage_names()
+        );
+    }
+
+    if pcm.is_empty() {
+        println!("[session {}] Audio data is empty.", id);
+        return processed(Outcome::Empty);
+    }
+
+    let frames = pcm.len() / usize::from(format.output_channels.max(1));
+    let duration_secs = frames as f64 / f64::from(sample_rate.max(1));
+    println!("[session {}] Duration: {:.2}s", id, duration_secs);
+    if duration_secs <= MIN_TRANSCRIBE_SECS {
+        return processed(Outcome::TooShort);
+    }
+
+    if config.vad.enabled {
+        match vad::detect_speech(&pcm, sample_rate, &config.vad) {
+            Some(speech) => {
+                println!(
+                    "[session {}] Speech found in samples {:?} of {}.",
+                    id,
+                    speech,
+                    pcm.len()
+                );
+                pcm = pcm[speech].to_vec();
+            }
+            None => {
+                println!(
+                    "[session {}] No speech detected. Skipping transcription.",
+                    id
+                );
+                return processed(Outcome::NoSpeech);
+            }
+        }
+    }
+
+    if config.loudness.normalize {
+        let gain_db = loudness::normalize(&mut pcm, sample_rate, &config.loudness);
+        println!(
+            "[session {}] Normalized loudness with {:+.1} dB gain.",
+            id, gain_db
+        );
+    }
+
+    processed(Outcome::Ready(WavPcm {
+        sample_rate,
+        channels: format.output_channels,
+        samples: pcm,
+    }))
+}
+
+async fn set_state(app_state: &AppStateRef, events: &dyn SessionEvents, state: RecorderState) {
+    *app_state.lock().await = state;
+    events.state_changed(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue::StartCueMode;
+    use crate::transcription::{
+        HealthStatus, ProviderHealth, ProviderKind, TranscriptionRequest, TranscriptionResponse,
+    };
+    use crate::wav;
+    use std::sync::Mutex;
+
+    const NATIVE_RATE: u32 = 48_000;
+    const OUTPUT_RATE: u32 = 16_000;
+
+    /// Returns canned audio, optionally only once it is stopped.
+    struct FakeSource {
+        samples: Vec<i16>,
+        error: Option<String>,
+        wait_for_stop: bool,
+    }
+
+    impl FakeSource {
+        fn new(samples: Vec<i16>) -> Box<Self> {
+            Box::new(Self {
+                samples,
+                error: None,
+                wait_for_stop: false,
+            })
+        }
+    }
+
+    impl AudioSource for FakeSource {
+        fn name(&self) -> String {
+            "fake".to_string()
+        }
+
+        fn capture(
+            self: Box<Self>,
+            stop: StopSignal,
+            on_level: &mut dyn FnMut(AudioLevel),
+            on_audio: &mut dyn FnMut(&[i16]),
+        ) -> Result<Capture, String> {
+            while self.wait_for_stop && !stop.wait(Duration::from_millis(10)) {}
+            on_level(AudioLevel {
+                rms: 0.2,
+                peak: 0.3,
+                elapsed_ms: 100,
+            });
+            on_audio(&self.samples);
+            Ok(Capture {
+                samples: self.samples,
+                error: self.error,
+                ..Capture::default()
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        State(RecorderState),
+        Level,
+        CaptureFailed(String),
+        Diagnostics { usable: bool },
+        NoSpeech,
+        Partial(String, String),
+        EndCue,
+        Delivered(OutputMode, WavPcm),
+    }
+
+    #[derive(Default)]
+    struct RecordedEvents(Mutex<Vec<Event>>);
+
+    impl RecordedEvents {
+        fn push(&self, event: Event) {
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn take(&self) -> Vec<Event> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl SessionEvents for RecordedEvents {
+        fn state_changed(&self, state: RecorderState) {
+            self.push(Event::State(state));
+        }
+        fn level(&self, _level: AudioLevel) {
+            self.push(Event::Level);
+        }
+        fn capture_failed(&self, _source: &str, error: &str) {
+            self.push(Event::CaptureFailed(error.to_string()));
+        }
+        fn overflow(&self, _dropped_samples: u64, _capacity_samples: usize) {}
+        fn diagnostics(&self, diagnostics: &RecordingDiagnostics) {
+            self.push(Event::Diagnostics {
+                usable: diagnostics.usable,
+            });
+        }
+        fn no_speech(&self, _session_id: u64) {
+            self.push(Event::NoSpeech);
+        }
+        fn partial_transcript(&self, _session_id: u64, partial: &PartialTranscript) {
+            self.push(Event::Partial(
+                partial.stable.clone(),
+                partial.unstable.clone(),
+            ));
+        }
+        fn end_cue(&self) {
+            self.push(Event::EndCue);
+        }
+        fn deliver(&self, delivery: Delivery) -> ProviderFuture<'_, Result<(), String>> {
+            Box::pin(async move {
+                // Delivery takes a while, as a transcription request would.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                self.push(Event::Delivered(delivery.output_mode, delivery.audio));
+                Ok(())
+            })
+        }
+    }
+
+    fn config(output_mode: OutputMode) -> SessionConfig {
+        SessionConfig {
+            output_mode,
+            format: AudioFormat {
+                native_sample_rate: NATIVE_RATE,
+                native_channels: 2,
+                output_sample_rate: OUTPUT_RATE,
+                output_channels: 1,
+            },
+            input_gain_db: 0.0,
+            start_cue: StartCueSettings {
+                mode: StartCueMode::Off,
+                ..StartCueSettings::default()
+            },
+            dsp: DspSettings::default(),
+            vad: VadSettings {
+                enabled: false,
+                ..VadSettings::default()
+            },
+            loudness: LoudnessSettings::default(),
+        }
+    }
+
+    /// Interleaved stereo 300 Hz tone at the native rate.
+    fn stereo_tone(secs: f64) -> Vec<i16> {
+        let frames = (secs * f64::from(NATIVE_RATE)) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f64 / f64::from(NATIVE_RATE);
+                let sample =
+                    (0.3 * f64::from(i16::MAX) * (2.0 * std::f64::consts::PI * 300.0 * t).sin())
+                        as i16;
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    async fn run(
+        session: RecordingSession,
+        source: Box<dyn AudioSource>,
+    ) -> (Vec<Event>, RecorderState) {
+        let events = Arc::new(RecordedEvents::default());
+        let app_state = AppStateRef::new(tokio::sync::Mutex::new(RecorderState::Recording));
+        session.run(source, events.clone(), app_state.clone()).await;
+        let final_state = *app_state.lock().await;
+        (events.take(), final_state)
+    }
+
+    #[tokio::test]
+    async fn speech_is_converted_and_delivered() {
+        let session = RecordingSession::new(
+            config(OutputMode::Clipboard),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let (events, final_state) = run(session, FakeSource::new(stereo_tone(2.0))).await;
+
+        assert_eq!(final_state, RecorderState::Idle);
+        let Some(Event::Delivered(mode, audio)) = events
+            .iter()
+            .find(|event| matches!(event, Event::Delivered(..)))
+        else {
+            panic!("nothing delivered: {:?}", events);
+        };
+        assert_eq!(*mode, OutputMode::Clipboard);
+        assert_eq!((audio.sample_rate, audio.channels), (OUTPUT_RATE, 1));
+        assert!((audio.samples.len() as i64 - 32_000).abs() < 100);
+
+        let stages: Vec<&Event> = events
+            .iter()
+            .filter(|event| !matches!(event, Event::Delivered(..)))
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                &Event::Level,
+                &Event::Diagnostics { usable: true },
+                &Event::State(RecorderState::Transcribing),
+                &Event::State(RecorderState::Idle),
+            ]
+        );
+        let delivered = events
+            .iter()
+            .position(|event| matches!(event, Event::Delivered(..)));
+        let idle = events
+            .iter()
+            .position(|event| *event == Event::State(RecorderState::Idle));
+        assert!(
+            delivered < idle,
+            "recorder went idle before delivery finished"
+        );
+    }
+
+    #[tokio::test]
+    async fn short_word_in_a_longer_press_is_kept() {
+        // A 0.4 s word with syllable-like loudness inside a 2 s press; once
+        // silence is trimmed, less than a second is left.
+        let word_start = NATIVE_RATE as usize * 3 / 4;
+        let mut samples = vec![0i16; 2 * 2 * NATIVE_RATE as usize];
+        for (frame, pair) in stereo_tone(0.4).chunks(2).enumerate() {
+            let t = frame as f64 / f64::from(NATIVE_RATE);
+            let envelope = (2.0 * std::f64::consts::PI * 4.0 * t).sin().abs();
+            let at = 2 * (word_start + frame);
+            samples[at] = (f64::from(pair[0]) * envelope) as i16;
+            samples[at + 1] = samples[at];
+        }
+
+        let mut config = config(OutputMode::Chat);
+        config.vad.enabled = true;
+        let session = RecordingSession::new(config, CueTiming::default(), StopSignal::default());
+        let (events, _) = run(session, FakeSource::new(samples)).await;
+
+        let Some(Event::Delivered(_, audio)) = events
+            .iter()
+            .find(|event| matches!(event, Event::Delivered(..)))
+        else {
+            panic!("nothing delivered: {:?}", events);
+        };
+        assert!(
+            audio.samples.len() < OUTPUT_RATE as usize,
+            "silence was not trimmed: {} samples",
+            audio.samples.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn steady_tone_is_reported_as_no_speech() {
+        let mut config = config(OutputMode::Chat);
+        config.vad.enabled = true;
+        let session = RecordingSession::new(config, CueTiming::default(), StopSignal::default());
+        let (events, final_state) = run(session, FakeSource::new(stereo_tone(2.0))).await;
+
+        assert_eq!(final_state, RecorderState::Idle);
+        assert_eq!(
+            events,
+            vec![
+                Event::Level,
+                Event::Diagnostics { usable: true },
+                Event::NoSpeech,
+                Event::EndCue,
+                Event::State(RecorderState::Idle),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replayed_wav_file_is_delivered() {
+        let path = std::env::temp_dir().join(format!("murmur-replay-{}.wav", std::process::id()));
+        std::fs::write(&path, wav::write_pcm16(&stereo_tone(2.0), 2, NATIVE_RATE)).unwrap();
+        let source = crate::source::FileSource::open_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let session = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let (events, final_state) = run(session, Box::new(source)).await;
+
+        assert_eq!(final_state, RecorderState::Idle);
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, Event::Delivered(OutputMode::Chat, _))),
+            "nothing delivered: {:?}",
+            events
+        );
+    }
+
+    /// Hears chunks and answers with how much audio it has heard.
+    #[derive(Default)]
+    struct FakeStreamingProvider {
+        chunks: Mutex<Vec<(usize, bool)>>,
+    }
+
+    impl TranscriptionProvider for FakeStreamingProvider {
+        fn id(&self) -> &str {
+            "streaming"
+        }
+
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::OpenAiCompatible
+        }
+
+        fn transcribe(
+            &self,
+            _request: TranscriptionRequest,
+        ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+            Box::pin(async { Err("recordings are delivered, not transcribed".to_string()) })
+        }
+
+        fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+            Box::pin(async {
+                ProviderHealth {
+                    provider: "streaming".to_string(),
+                    kind: ProviderKind::OpenAiCompatible,
+                    status: HealthStatus::Ok,
+                    detail: None,
+                    latency_ms: None,
+                }
+            })
+        }
+
+        fn supports_streaming(&self) -> bool {
+            true
+        }
+
+        fn stream_chunk(
+            &self,
+            chunk: streaming_transcription::StreamChunk,
+        ) -> ProviderFuture<'_, Result<PartialTranscript, String>> {
+            Box::pin(async move {
+                let mut chunks = self.chunks.lock().unwrap();
+                chunks.push((chunk.pcm.len(), chunk.is_final));
+                let heard: usize = chunks.iter().map(|(samples, _)| samples).sum();
+                Ok(PartialTranscript {
+                    stable: format!("{} ms", heard * 1000 / chunk.sample_rate as usize),
+                    unstable: String::new(),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn captured_audio_is_streamed_before_delivery() {
+        let path = std::env::temp_dir().join(format!("murmur-stream-{}.wav", std::process::id()));
+        std::fs::write(&path, wav::write_pcm16(&stereo_tone(2.0), 2, NATIVE_RATE)).unwrap();
+        let source = crate::source::FileSource::open_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let provider = Arc::new(FakeStreamingProvider::default());
+        let session = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        )
+        .stream_to(provider.clone(), Duration::from_millis(500));
+        let (events, _) = run(session, Box::new(source)).await;
+
+        let chunks = provider.chunks.lock().unwrap().clone();
+        let samples: usize = chunks.iter().map(|(samples, _)| samples).sum();
+        assert_eq!(samples, 2 * OUTPUT_RATE as usize);
+        let finals: Vec<bool> = chunks.iter().map(|(_, is_final)| *is_final).collect();
+        assert_eq!(finals.iter().filter(|is_final| **is_final).count(), 1);
+        assert_eq!(finals.last(), Some(&true));
+
+        let delivered = events
+            .iter()
+            .position(|event| matches!(event, Event::Delivered(..)))
+            .expect("nothing delivered");
+        let last_partial = events
+            .iter()
+            .rposition(|event| matches!(event, Event::Partial(..)))
+            .expect("no partial transcripts");
+        assert!(last_partial < delivered);
+        assert_eq!(
+            events[last_partial],
+            Event::Partial("2000 ms".to_string(), String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn short_recording_plays_end_cue_instead_of_delivering() {
+        let session = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let (events, final_state) = run(session, FakeSource::new(stereo_tone(0.5))).await;
+
+        assert_eq!(final_state, RecorderState::Idle);
+        assert_eq!(
+            events,
+            vec![
+                Event::Level,
+                Event::Diagnostics { usable: true },
+                Event::EndCue,
+                Event::State(RecorderState::Idle),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_recording_is_not_processed() {
+        let session = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let (events, _) = run(session, FakeSource::new(vec![0; 192_000])).await;
+
+        assert!(events.contains(&Event::Diagnostics { usable: false }));
+        assert!(events.contains(&Event::EndCue));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::Delivered(..))));
+    }
+
+    #[tokio::test]
+    async fn audio_captured_before_a_stream_error_is_kept() {
+        let session = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let source = Box::new(FakeSource {
+            samples: stereo_tone(2.0),
+            error: Some("device unplugged".to_string()),
+            wait_for_stop: false,
+        });
+        let (events, _) = run(session, source).await;
+
+        assert!(events.contains(&Event::CaptureFailed("device unplugged".to_string())));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Delivered(OutputMode::Chat, _))));
+    }
+
+    #[tokio::test]
+    async fn stop_signal_ends_capture() {
+        let stop = StopSignal::default();
+        let session =
+            RecordingSession::new(config(OutputMode::Chat), CueTiming::default(), stop.clone());
+        let source = Box::new(FakeSource {
+            samples: stereo_tone(2.0),
+            error: None,
+            wait_for_stop: true,
+        });
+        let running = tokio::spawn(run(session, source));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!running.is_finished());
+        stop.stop();
+        let (events, final_state) = tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .expect("session did not finish after stop")
+            .unwrap();
+        assert_eq!(final_state, RecorderState::Idle);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Delivered(..))));
+    }
+
+    #[tokio::test]
+    async fn stop_before_start_records_nothing() {
+        // A quick release in hold mode can stop the session while the device
+        // is still being opened.
+        let stop = StopSignal::default();
+        stop.stop();
+        let session = RecordingSession::new(config(OutputMode::Chat), CueTiming::default(), stop);
+        let source = Box::new(FakeSource {
+            samples: stereo_tone(2.0),
+            error: None,
+            wait_for_stop: true,
+        });
+        let (events, final_state) =
+            tokio::time::timeout(Duration::from_secs(5), run(session, source))
+                .await
+                .expect("session did not finish");
+        assert_eq!(final_state, RecorderState::Idle);
+        assert_eq!(events, vec![Event::State(RecorderState::Idle)]);
+    }
+
+    #[test]
+    fn sessions_get_distinct_ids() {
+        let first = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        let second = RecordingSession::new(
+            config(OutputMode::Chat),
+            CueTiming::default(),
+            StopSignal::default(),
+        );
+        assert!(second.id > first.id);
+    }
+}
@@ -0,0 +1,76 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tauri_plugin_store::StoreExt;
+
+pub(crate) const SETTINGS_STORE: &str = "settings.json";
+
+pub(crate) const SELECTED_AUDIO_DEVICE_KEY: &str = "selected_audio_device";
+pub(crate) const AUDIO_DEVICE_PRIORITY_KEY: &str = "audio_device_priority";
+pub(crate) const AUDIO_DEVICE_GAIN_KEY: &str = "audio_device_gain";
+pub(crate) const OUTPUT_SAMPLE_RATE_KEY: &str = "output_sample_rate";
+pub(crate) const CAPTURE_BUFFER_MS_KEY: &str = "capture_buffer_ms";
+pub(crate) const CAPTURE_FORMAT_KEY: &str = "capture_format";
+pub(crate) const VAD_SETTINGS_KEY: &str = "vad";
+pub(crate) const HANDS_FREE_SETTINGS_KEY: &str = "hands_free";
+pub(crate) const SHORTCUT_ACTIVATION_KEY: &str = "shortcut_activation";
+pub(crate) const WARM_MICROPHONE_KEY: &str = "warm_microphone";
+pub(crate) const START_CUE_KEY: &str = "start_cue";
+pub(crate) const DSP_SETTINGS_KEY: &str = "dsp";
+pub(crate) const LOUDNESS_SETTINGS_KEY: &str = "loudness";
+pub(crate) const USE_LOCAL_MODE_KEY: &str = "use_local_mode";
+pub(crate) const BACKEND_URL_KEY: &str = "backend_url";
+pub(crate) const TRANSCRIPTION_SETTINGS_KEY: &str = "transcription";
+pub(crate) const LOCAL_TRANSCRIPTION_SETTINGS_KEY: &str = "local_transcription";
+pub(crate) const TRANSCRIPTION_PROVIDERS_KEY: &str = "transcription_providers";
+pub(crate) const TRANSCRIPTION_PROVIDER_KEY: &str = "transcription_provider";
+pub(crate) const TRANSCRIPTION_CHUNKING_KEY: &str = "transcription_chunking";
+pub(crate) const STREAMING_TRANSCRIPTION_KEY: &str = "streaming_transcription";
+
+pub(crate) fn read<T: DeserializeOwned>(app_handle: &tauri::AppHandle, key: &str) -> Option<T> {
+    let store = match app_handle.store(SETTINGS_STORE) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("[STORE] Failed to open {}: {}", SETTINGS_STORE, e);
+            return None;
+        }
+    };
+
+    let value = store.get(key)?;
+    match serde_json::from_value(value) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            eprintln!("[STORE] Ignoring invalid value for '{}': {}", key, e);
+            None
+        }
+    }
+}
+
+pub(crate) fn write<T: Serialize>(
+    app_handle: &tauri::AppHandle,
+    key: &str,
+    value: &T,
+) -> Result<(), String> {
+    let store = app_handle
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", SETTINGS_STORE, e))?;
+    let value =
+        serde_json::to_value(value).map_err(|e| format!("Failed to serialize '{}': {}", key, e))?;
+
+    store.set(key, value);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save {}: {}", SETTINGS_STORE, e))
+}
+
+pub(crate) fn remove(app_handle: &tauri::AppHandle, key: &str) -> Result<(), String> {
+    let store = app_handle
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", SETTINGS_STORE, e))?;
+
+    if store.delete(key) {
+        store
+            .save()
+            .map_err(|e| format!("Failed to save {}: {}", SETTINGS_STORE, e))?;
+    }
+    Ok(())
+}
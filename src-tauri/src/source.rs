@@ -0,0 +1,378 @@
+//! Where recording sessions get their audio: a live cpal device, a WAV or
+//! raw PCM file, or a synthetic signal. File and synthetic sources let the
+//! whole pipeline run without a microphone.
+
+use crate::audio::{self, AudioLevel, Capture, CaptureOptions, LevelMeter, StopSignal};
+use crate::wav;
+use cpal::traits::DeviceTrait;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Where a session's audio comes from. Runs on the session's capture thread.
+pub(crate) trait AudioSource: Send + 'static {
+    /// Names the source in logs and error events.
+    fn name(&self) -> String;
+
+    /// Records until `stop` fires or the source ends on its own. `on_audio`
+    /// sees the interleaved samples in order as they are captured.
+    fn capture(
+        self: Box<Self>,
+        stop: StopSignal,
+        on_level: &mut dyn FnMut(AudioLevel),
+        on_audio: &mut dyn FnMut(&[i16]),
+    ) -> Result<Capture, String>;
+}
+
+/// Live capture from a cpal input device.
+pub(crate) struct CpalSource {
+    pub(crate) device: cpal::Device,
+    pub(crate) config: cpal::SupportedStreamConfig,
+    pub(crate) capacity_samples: usize,
+    pub(crate) options: CaptureOptions,
+}
+
+impl AudioSource for CpalSource {
+    fn name(&self) -> String {
+        self.device
+            .name()
+            .unwrap_or_else(|_| "Unknown device".to_string())
+    }
+
+    fn capture(
+        self: Box<Self>,
+        stop: StopSignal,
+        on_level: &mut dyn FnMut(AudioLevel),
+        on_audio: &mut dyn FnMut(&[i16]),
+    ) -> Result<Capture, String> {
+        audio::record_audio_stream(
+            stop,
+            self.capacity_samples,
+            self.device,
+            self.config,
+            self.options,
+            on_level,
+            on_audio,
+        )
+    }
+}
+
+/// Hands `samples` over in level-report sized chunks. In real time each
+/// chunk waits for its playback time, so stopping early keeps only what
+/// would have been spoken so far.
+fn play_back(
+    samples: Vec<i16>,
+    sample_rate: u32,
+    channels: u16,
+    realtime: bool,
+    stop: &StopSignal,
+    on_level: &mut dyn FnMut(AudioLevel),
+    on_audio: &mut dyn FnMut(&[i16]),
+) -> Capture {
+    let chunk_frames = (u64::from(sample_rate) * audio::LEVEL_REPORT_INTERVAL.as_millis() as u64
+        / 1000)
+        .max(1) as usize;
+    let chunk_len = chunk_frames * usize::from(channels.max(1));
+    let level_meter = LevelMeter::default();
+    let started_at = Instant::now();
+
+    let mut played = 0;
+    for chunk in samples.chunks(chunk_len) {
+        if stop.is_stopped() {
+            break;
+        }
+        level_meter.record(chunk);
+        played += chunk.len();
+        let position = Duration::from_secs_f64(
+            (played / usize::from(channels.max(1))) as f64 / f64::from(sample_rate.max(1)),
+        );
+        if realtime && stop.wait(position.saturating_sub(started_at.elapsed())) {
+            break;
+        }
+        on_level(level_meter.take(position));
+        on_audio(chunk);
+    }
+
+    let mut samples = samples;
+    samples.truncate(played);
+    Capture {
+        samples,
+        ..Capture::default()
+    }
+}
+
+/// Audio read from a 16-bit WAV file or headerless 16-bit little-endian PCM.
+pub(crate) struct FileSource {
+    name: String,
+    pcm: wav::WavPcm,
+    realtime: bool,
+}
+
+impl FileSource {
+    pub(crate) fn open_wav(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        let pcm = wav::read_pcm16(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            name: path.display().to_string(),
+            pcm,
+            realtime: false,
+        })
+    }
+
+    pub(crate) fn open_raw(path: &Path, sample_rate: u32, channels: u16) -> Result<Self, String> {
+        if sample_rate == 0 || channels == 0 {
+            return Err("Raw PCM needs a sample rate and channel count".to_string());
+        }
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        let samples = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        Ok(Self {
+            name: path.display().to_string(),
+            pcm: wav::WavPcm {
+                sample_rate,
+                channels,
+                samples,
+            },
+            realtime: false,
+        })
+    }
+
+    /// Plays the file at its natural speed instead of all at once.
+    pub(crate) fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.pcm.sample_rate
+    }
+
+    pub(crate) fn channels(&self) -> u16 {
+        self.pcm.channels
+    }
+}
+
+impl AudioSource for FileSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn capture(
+        self: Box<Self>,
+        stop: StopSignal,
+        on_level: &mut dyn FnMut(AudioLevel),
+        on_audio: &mut dyn FnMut(&[i16]),
+    ) -> Result<Capture, String> {
+        let wav::WavPcm {
+            sample_rate,
+            channels,
+            samples,
+        } = self.pcm;
+        Ok(play_back(
+            samples,
+            sample_rate,
+            channels,
+            self.realtime,
+            &stop,
+            on_level,
+            on_audio,
+        ))
+    }
+}
+
+/// One stretch of a synthetic signal.
+#[cfg(test)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Segment {
+    Tone { hz: f32, amplitude: f32, ms: u32 },
+    Noise { amplitude: f32, ms: u32 },
+    Silence { ms: u32 },
+}
+
+/// Generated audio, the same on every channel.
+#[cfg(test)]
+pub(crate) struct SyntheticSource {
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u16,
+    pub(crate) segments: Vec<Segment>,
+    pub(crate) realtime: bool,
+}
+
+#[cfg(test)]
+impl SyntheticSource {
+    /// Interleaved samples for every segment in order.
+    fn generate(&self) -> Vec<i16> {
+        let full_scale = f32::from(i16::MAX);
+        let rate = self.sample_rate.max(1);
+        // Fixed-seed LCG, so runs are reproducible.
+        let mut noise_state = 0x2545_f491u32;
+        let mut samples = Vec::new();
+        for segment in &self.segments {
+            let ms = match *segment {
+                Segment::Tone { ms, .. } | Segment::Noise { ms, .. } | Segment::Silence { ms } => {
+                    ms
+                }
+            };
+            let frames = (u64::from(rate) * u64::from(ms) / 1000) as usize;
+            for frame in 0..frames {
+                let value = match *segment {
+                    Segment::Tone { hz, amplitude, .. } => {
+                        let t = frame as f32 / rate as f32;
+                        amplitude * (2.0 * std::f32::consts::PI * hz * t).sin()
+                    }
+                    Segment::Noise { amplitude, .. } => {
+                        noise_state = noise_state
+                            .wrapping_mul(1_664_525)
+                            .wrapping_add(1_013_904_223);
+                        amplitude * ((noise_state >> 8) as f32 / (1u32 << 23) as f32 - 1.0)
+                    }
+                    Segment::Silence { .. } => 0.0,
+                };
+                let sample = (value.clamp(-1.0, 1.0) * full_scale).round() as i16;
+                samples.extend(std::iter::repeat_n(
+                    sample,
+                    usize::from(self.channels.max(1)),
+                ));
+            }
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+impl AudioSource for SyntheticSource {
+    fn name(&self) -> String {
+        format!("synthetic ({} segments)", self.segments.len())
+    }
+
+    fn capture(
+        self: Box<Self>,
+        stop: StopSignal,
+        on_level: &mut dyn FnMut(AudioLevel),
+        on_audio: &mut dyn FnMut(&[i16]),
+    ) -> Result<Capture, String> {
+        let samples = self.generate();
+        Ok(play_back(
+            samples,
+            self.sample_rate,
+            self.channels,
+            self.realtime,
+            &stop,
+            on_level,
+            on_audio,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(source: Box<dyn AudioSource>) -> (Capture, usize) {
+        let mut levels = 0;
+        let mut streamed = Vec::new();
+        let capture = source
+            .capture(
+                StopSignal::default(),
+                &mut |_| levels += 1,
+                &mut |samples| streamed.extend_from_slice(samples),
+            )
+            .unwrap();
+        assert_eq!(streamed, capture.samples);
+        (capture, levels)
+    }
+
+    #[test]
+    fn wav_file_is_returned_in_full() {
+        let pcm: Vec<i16> = (0..48_000).map(|i| (i % 2000) as i16 - 1000).collect();
+        let path = std::env::temp_dir().join(format!("murmur-source-{}.wav", std::process::id()));
+        std::fs::write(&path, wav::write_pcm16(&pcm, 2, 24_000)).unwrap();
+
+        let source = FileSource::open_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((source.sample_rate(), source.channels()), (24_000, 2));
+        let (capture, levels) = capture(Box::new(source));
+        assert_eq!(capture.samples, pcm);
+        // One second of audio in 100 ms level windows.
+        assert_eq!(levels, 10);
+    }
+
+    #[test]
+    fn raw_pcm_file_uses_the_given_format() {
+        let pcm: Vec<i16> = vec![1, -2, 300, -4000, i16::MAX, i16::MIN];
+        let bytes: Vec<u8> = pcm.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let path = std::env::temp_dir().join(format!("murmur-source-{}.pcm", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+
+        let source = FileSource::open_raw(&path, 16_000, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(capture(Box::new(source)).0.samples, pcm);
+        assert!(FileSource::open_raw(&path, 0, 1).is_err());
+    }
+
+    #[test]
+    fn synthetic_segments_have_expected_length_and_level() {
+        let source = SyntheticSource {
+            sample_rate: 16_000,
+            channels: 2,
+            segments: vec![
+                Segment::Silence { ms: 250 },
+                Segment::Tone {
+                    hz: 440.0,
+                    amplitude: 0.5,
+                    ms: 500,
+                },
+                Segment::Noise {
+                    amplitude: 0.1,
+                    ms: 250,
+                },
+            ],
+            realtime: false,
+        };
+        let (capture, _) = capture(Box::new(source));
+        assert_eq!(capture.samples.len(), 16_000 * 2);
+        assert!(capture.samples[..8_000].iter().all(|&s| s == 0));
+        let tone_peak = capture.samples[8_000..24_000]
+            .iter()
+            .map(|s| s.unsigned_abs())
+            .max()
+            .unwrap();
+        assert!((16_000..=16_500).contains(&tone_peak), "peak {}", tone_peak);
+        let noise_peak = capture.samples[24_000..]
+            .iter()
+            .map(|s| s.unsigned_abs())
+            .max()
+            .unwrap();
+        assert!((1_000..=3_300).contains(&noise_peak), "peak {}", noise_peak);
+    }
+
+    #[test]
+    fn realtime_playback_stops_early() {
+        let source = Box::new(SyntheticSource {
+            sample_rate: 16_000,
+            channels: 1,
+            segments: vec![Segment::Tone {
+                hz: 200.0,
+                amplitude: 0.3,
+                ms: 10_000,
+            }],
+            realtime: true,
+        });
+        let stop = StopSignal::default();
+        let stopper = stop.clone();
+        let timer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            stopper.stop();
+        });
+
+        let started = Instant::now();
+        let capture = source.capture(stop, &mut |_| {}, &mut |_| {}).unwrap();
+        timer.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!capture.samples.is_empty());
+        assert!(capture.samples.len() < 16_000, "{}", capture.samples.len());
+    }
+}
@@ -1,25 +1,29 @@
-use std::sync::atomic::AtomicBool;
+use crate::activation::RecordingShortcut;
+use crate::audio::StopSignal;
+use crate::local_transcription::LocalEngine;
+use crate::preroll::WarmMicrophone;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::Mutex;
 
-#[derive(Debug, Clone)]
-pub(crate) struct AudioConfig {
-    pub(crate) sample_rate: u32,
-    pub(crate) channels: u16,
+/// The capture session in progress: the shortcut that started it, whose
+/// activation mode decides when it ends, and the signal that stops it.
+pub(crate) struct ActiveRecording {
+    pub(crate) shortcut: RecordingShortcut,
+    pub(crate) stop: StopSignal,
 }
 
-impl AudioConfig {
-    pub(crate) fn new() -> Self {
-        Self {
-            sample_rate: 44100,
-            channels: 1,
-        }
-    }
-}
+/// The capture session in progress, if any.
+pub(crate) type RecordingStopRef = Arc<Mutex<Option<ActiveRecording>>>;
+
+/// When the shortcut that started the current recording was pressed.
+pub type RecordingStartedAtRef = Arc<Mutex<Option<Instant>>>;
 
-pub type AudioConfigRef = Arc<Mutex<AudioConfig>>;
+/// The always-open pre-roll stream, present while the warm microphone is on.
+pub(crate) type WarmMicrophoneRef = Arc<Mutex<Option<Arc<WarmMicrophone>>>>;
 
-pub type RecordingFlag = Arc<AtomicBool>;
+/// The on-device transcription engine and the model it has loaded.
+pub(crate) type LocalEngineRef = Arc<Mutex<LocalEngine>>;
 
 #[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -0,0 +1,405 @@
+//! Partial transcripts while the user is still speaking. Providers that
+//! support it (see [`TranscriptionProvider::supports_streaming`]) are sent
+//! the captured audio in short chunks during the recording; each reply is the
+//! transcript so far, split into text the provider will not revise (`stable`)
+//! and its current guess at the rest (`unstable`).
+
+use crate::resample;
+use crate::session::AudioFormat;
+use crate::transcription::TranscriptionProvider;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Whether recordings stream to the active transcription provider, when it
+/// supports streaming. Stored under
+/// [`crate::settings::STREAMING_TRANSCRIPTION_KEY`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct StreamingSettings {
+    pub(crate) enabled: bool,
+    /// Audio collected before the next chunk is sent. Chunks grow past this
+    /// when the provider answers slower than the user speaks.
+    pub(crate) chunk_ms: u64,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chunk_ms: 400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct PartialTranscript {
+    /// Text that later chunks will not change.
+    pub(crate) stable: String,
+    /// The provider's current guess at the audio after `stable`.
+    pub(crate) unstable: String,
+}
+
+/// One chunk of a stream, in the output format.
+#[derive(Debug, Clone)]
+pub(crate) struct StreamChunk {
+    pub(crate) stream_id: String,
+    /// Counts up from 0 within a stream.
+    pub(crate) seq: u64,
+    pub(crate) sample_rate: u32,
+    /// Mono PCM captured since the previous chunk.
+    pub(crate) pcm: Vec<i16>,
+    /// Set on the last chunk, once the recording has stopped.
+    pub(crate) is_final: bool,
+}
+
+/// A stream ID that stays unique across app restarts, which reset session IDs.
+pub(crate) fn stream_id(session_id: u64) -> String {
+    let started_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+    format!("{:x}-{}", started_ms, session_id)
+}
+
+/// Sends capture-format audio from `audio` to `provider` in chunks of at
+/// least `chunk`, converted to the output format, and reports each changed
+/// partial transcript. Audio that arrives while a request is in flight goes
+/// into the next chunk. Once `audio` closes the rest is sent as the final
+/// chunk, and the last partial is returned.
+///
+/// One resampler runs across the whole stream, so the chunks join without
+/// seams and add up to the length of the recording. Each chunk holds back the
+/// few output samples whose kernel still needs the next chunk's input.
+pub(crate) async fn stream_partials(
+    provider: Arc<dyn TranscriptionProvider>,
+    stream_id: String,
+    format: AudioFormat,
+    chunk: Duration,
+    mut audio: UnboundedReceiver<Vec<i16>>,
+    mut on_partial: impl FnMut(&PartialTranscript),
+) -> Result<PartialTranscript, String> {
+    let channels = usize::from(format.native_channels.max(1));
+    let chunk_samples =
+        (chunk.as_secs_f64() * f64::from(format.native_sample_rate)) as usize * channels;
+
+    let mut resampler = Some(resample::Resampler::new(
+        format.native_sample_rate,
+        format.output_sample_rate,
+    ));
+    let mut latest = PartialTranscript::default();
+    let mut pending: Vec<i16> = Vec::new();
+    let mut seq = 0;
+    loop {
+        let is_final = match audio.recv().await {
+            Some(samples) => {
+                pending.extend(samples);
+                while let Ok(samples) = audio.try_recv() {
+                    pending.extend(samples);
+                }
+                if pending.len() < chunk_samples.max(channels) {
+                    continue;
+                }
+                false
+            }
+            None => true,
+        };
+
+        let whole_frames = pending.len() - pending.len() % channels;
+        let mono = resample::downmix_to_mono(&pending[..whole_frames], format.native_channels);
+        pending.drain(..whole_frames);
+        let mut pcm = Vec::new();
+        if let Some(resampler) = resampler.as_mut() {
+            pcm = resampler.process(&mono);
+        }
+        if is_final {
+            pcm.extend(
+                resampler
+                    .take()
+                    .map(resample::Resampler::finish)
+                    .unwrap_or_default(),
+            );
+        }
+        let partial = provider
+            .stream_chunk(StreamChunk {
+                stream_id: stream_id.clone(),
+                seq,
+                sample_rate: format.output_sample_rate,
+                pcm,
+                is_final,
+            })
+            .await?;
+        seq += 1;
+        if partial != latest {
+            on_partial(&partial);
+            latest = partial;
+        }
+        if is_final {
+            return Ok(latest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_transcription::{HttpProvider, TranscriptionSettings};
+    use crate::transcription::{
+        HealthStatus, ProviderFuture, ProviderHealth, ProviderKind, TranscriptionRequest,
+        TranscriptionResponse,
+    };
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    /// Reads one HTTP request; returns its request line and body.
+    fn read_request(stream: &mut TcpStream) -> (String, Vec<u8>) {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if request.len() >= header_end + 4 + content_length || n == 0 {
+                    let request_line = text.lines().next().unwrap_or_default().to_string();
+                    return (request_line, request[header_end + 4..].to_vec());
+                }
+            }
+            if n == 0 {
+                return (text, Vec::new());
+            }
+        }
+    }
+
+    /// A streaming server that hears one word per chunk. Each reply makes
+    /// the newest word unstable and everything before it stable, and the
+    /// final reply makes all of it stable. Returns the request lines and
+    /// body sizes it saw once the final chunk has arrived.
+    fn serve_stream() -> (String, thread::JoinHandle<Vec<(String, usize)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1/audio/stream", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            let mut words: Vec<String> = Vec::new();
+            loop {
+                let (mut stream, _) = listener.accept().unwrap();
+                let (request_line, body) = read_request(&mut stream);
+                let is_final = request_line.contains("final=true");
+                words.push(format!("word{}", words.len()));
+                let reply = if is_final {
+                    PartialTranscript {
+                        stable: words.join(" "),
+                        unstable: String::new(),
+                    }
+                } else {
+                    PartialTranscript {
+                        stable: words[..words.len() - 1].join(" "),
+                        unstable: words[words.len() - 1].clone(),
+                    }
+                };
+                let body_json = serde_json::to_string(&reply).unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body_json.len(),
+                    body_json
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push((request_line, body.len()));
+                if is_final {
+                    return requests;
+                }
+            }
+        });
+        (url, server)
+    }
+
+    /// A provider that streams to `url`.
+    fn http_provider(url: String) -> HttpProvider {
+        HttpProvider::new(
+            "backend",
+            "http://127.0.0.1:1",
+            TranscriptionSettings {
+                streaming_url: Some(url),
+                ..TranscriptionSettings::default()
+            },
+        )
+    }
+
+    fn format() -> AudioFormat {
+        AudioFormat {
+            native_sample_rate: 48_000,
+            native_channels: 2,
+            output_sample_rate: 16_000,
+            output_channels: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn partials_arrive_while_audio_is_streaming() {
+        let (url, server) = serve_stream();
+        let provider = Arc::new(http_provider(url));
+        let (audio_tx, audio_rx) = unbounded_channel();
+        let (partial_tx, mut partials) = unbounded_channel();
+        let streaming = tokio::spawn(stream_partials(
+            provider,
+            "test-stream".to_string(),
+            format(),
+            Duration::from_millis(300),
+            audio_rx,
+            move |partial: &PartialTranscript| {
+                let _ = partial_tx.send(partial.clone());
+            },
+        ));
+
+        for word in 0..3 {
+            // 300 ms of 48 kHz stereo in 100 ms capture buffers.
+            for _ in 0..3 {
+                audio_tx.send(vec![0; 9_600]).unwrap();
+            }
+            let partial = partials.recv().await.unwrap();
+            assert_eq!(partial.unstable, format!("word{}", word));
+        }
+        audio_tx.send(vec![0; 4_800]).unwrap();
+        drop(audio_tx);
+
+        let last = streaming.await.unwrap().unwrap();
+        assert_eq!(last.stable, "word0 word1 word2 word3");
+        assert!(last.unstable.is_empty());
+        assert_eq!(partials.recv().await, Some(last));
+
+        let requests = server.join().unwrap();
+        let finals: Vec<bool> = requests
+            .iter()
+            .map(|(line, _)| line.contains("final=true"))
+            .collect();
+        assert_eq!(finals, vec![false, false, false, true]);
+        for (seq, (line, _)) in requests.iter().enumerate() {
+            assert!(line.starts_with("POST /v1/audio/stream?stream_id=test-stream"));
+            assert!(line.contains(&format!("&seq={}&sample_rate=16000&", seq)));
+        }
+        let body_bytes: Vec<usize> = requests.iter().map(|(_, bytes)| *bytes).collect();
+        // 300 ms chunks and a 50 ms tail of 16 kHz mono 16-bit PCM. The first
+        // chunk holds back the samples the resampler needs more input for;
+        // the final chunk catches up to exactly the recording's length.
+        assert!((9_000..9_600).contains(&body_bytes[0]), "{:?}", body_bytes);
+        assert_eq!(&body_bytes[1..3], &[9_600, 9_600]);
+        assert_eq!(body_bytes.iter().sum::<usize>(), 3 * 9_600 + 1_600);
+    }
+
+    /// Answers every chunk and keeps the PCM it was sent.
+    #[derive(Default)]
+    struct RecordingProvider {
+        pcm: std::sync::Mutex<Vec<i16>>,
+    }
+
+    impl TranscriptionProvider for RecordingProvider {
+        fn id(&self) -> &str {
+            "recording"
+        }
+
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::OpenAiCompatible
+        }
+
+        fn transcribe(
+            &self,
+            _request: TranscriptionRequest,
+        ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+            Box::pin(async { Err("not used".to_string()) })
+        }
+
+        fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+            Box::pin(async {
+                ProviderHealth {
+                    provider: "recording".to_string(),
+                    kind: ProviderKind::OpenAiCompatible,
+                    status: HealthStatus::Ok,
+                    detail: None,
+                    latency_ms: None,
+                }
+            })
+        }
+
+        fn supports_streaming(&self) -> bool {
+            true
+        }
+
+        fn stream_chunk(
+            &self,
+            chunk: StreamChunk,
+        ) -> ProviderFuture<'_, Result<PartialTranscript, String>> {
+            self.pcm.lock().unwrap().extend(chunk.pcm);
+            Box::pin(async { Ok(PartialTranscript::default()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn chunks_join_into_the_whole_recording_resampled() {
+        // A 440 Hz tone at 48 kHz stereo, captured in uneven buffers.
+        let mono: Vec<i16> = (0..48_000)
+            .map(|i| {
+                let t = i as f32 / 48_000.0;
+                (8_000.0 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()) as i16
+            })
+            .collect();
+        let stereo: Vec<i16> = mono.iter().flat_map(|&s| [s, s]).collect();
+
+        let provider = Arc::new(RecordingProvider::default());
+        let (audio_tx, audio_rx) = unbounded_channel();
+        for buffer in stereo.chunks(2 * 1_237) {
+            audio_tx.send(buffer.to_vec()).unwrap();
+        }
+        drop(audio_tx);
+        stream_partials(
+            provider.clone(),
+            "test-stream".to_string(),
+            format(),
+            Duration::from_millis(100),
+            audio_rx,
+            |_: &PartialTranscript| {},
+        )
+        .await
+        .unwrap();
+
+        let streamed = provider.pcm.lock().unwrap().clone();
+        assert_eq!(streamed, resample::resample_mono(&mono, 48_000, 16_000));
+    }
+
+    #[tokio::test]
+    async fn failed_request_ends_the_stream() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/stream", listener.local_addr().unwrap())
+        };
+        let provider = Arc::new(http_provider(url));
+        let (audio_tx, audio_rx) = unbounded_channel();
+        audio_tx.send(vec![0; 96_000]).unwrap();
+
+        let result = stream_partials(
+            provider,
+            "test-stream".to_string(),
+            format(),
+            Duration::from_millis(300),
+            audio_rx,
+            |_: &PartialTranscript| panic!("no partial expected"),
+        )
+        .await;
+        assert!(result.unwrap_err().starts_with("Streaming request to"));
+        // Later audio is dropped; the capture thread ignores the send error.
+        assert!(audio_tx.send(vec![0; 9_600]).is_err());
+    }
+}
@@ -0,0 +1,581 @@
+//! Speech-to-text providers behind one interface. Each provider turns a WAV
+//! recording into a [`TranscriptionResponse`], and some can also transcribe
+//! a recording while it is captured; the [`ProviderRegistry`] holds the
+//! configured ones and picks which one new recordings use.
+
+use crate::chunking::{self, AudioChunk, ChunkingSettings};
+use crate::streaming_transcription::{PartialTranscript, StreamChunk};
+use crate::wav::{self, WavPcm};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// ID of the provider built from `backend_url`.
+pub(crate) const BACKEND_PROVIDER_ID: &str = "backend";
+/// ID of the on-device provider.
+pub(crate) const LOCAL_PROVIDER_ID: &str = "local";
+
+pub(crate) type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProviderKind {
+    OpenAiCompatible,
+    Local,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TranscriptionRequest {
+    /// 16-bit PCM WAV.
+    pub(crate) wav: Vec<u8>,
+    /// Overrides the provider's configured language.
+    pub(crate) language: Option<String>,
+    /// Overrides the provider's configured prompt, where supported.
+    pub(crate) prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub(crate) struct TranscriptSegment {
+    pub(crate) start_ms: u64,
+    pub(crate) end_ms: u64,
+    pub(crate) text: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub(crate) struct TranscriptionTiming {
+    /// Length of the transcribed audio.
+    pub(crate) audio_ms: u64,
+    /// Wall time the provider took.
+    pub(crate) processing_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub(crate) struct TranscriptionResponse {
+    pub(crate) text: String,
+    /// Language the provider reports, when it reports one.
+    pub(crate) language: Option<String>,
+    /// Timed segments, empty when the provider only returns text.
+    pub(crate) segments: Vec<TranscriptSegment>,
+    /// Filled in by the registry.
+    pub(crate) timing: TranscriptionTiming,
+    /// ID of the provider that produced the transcript.
+    pub(crate) provider: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HealthStatus {
+    Ok,
+    /// Settings are missing or invalid, such as no URL or model file.
+    Misconfigured,
+    /// Support for the provider is not compiled into this build.
+    Unavailable,
+    Unauthorized,
+    ServerError,
+    Timeout,
+    ConnectionFailed,
+    NetworkError,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct ProviderHealth {
+    pub(crate) provider: String,
+    pub(crate) kind: ProviderKind,
+    pub(crate) status: HealthStatus,
+    pub(crate) detail: Option<String>,
+    /// Round trip of the check, for providers reached over the network.
+    pub(crate) latency_ms: Option<u64>,
+}
+
+pub(crate) trait TranscriptionProvider: Send + Sync {
+    fn id(&self) -> &str;
+
+    fn kind(&self) -> ProviderKind;
+
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+    ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>>;
+
+    /// Checks the provider could take a request now, without transcribing.
+    fn health(&self) -> ProviderFuture<'_, ProviderHealth>;
+
+    /// Whether the provider takes [`TranscriptionProvider::stream_chunk`].
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    /// Sends the next chunk of a recording in progress and returns the
+    /// transcript of the stream so far.
+    fn stream_chunk(
+        &self,
+        _chunk: StreamChunk,
+    ) -> ProviderFuture<'_, Result<PartialTranscript, String>> {
+        let message = format!("Provider '{}' does not support streaming", self.id());
+        Box::pin(async move { Err(message) })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct ProviderInfo {
+    pub(crate) id: String,
+    pub(crate) kind: ProviderKind,
+    pub(crate) active: bool,
+}
+
+/// Length of a recording.
+fn audio_ms(pcm: &WavPcm) -> u64 {
+    let frames = pcm.samples.len() as u64 / u64::from(pcm.channels.max(1));
+    frames * 1000 / u64::from(pcm.sample_rate.max(1))
+}
+
+pub(crate) struct ProviderRegistry {
+    providers: Vec<Arc<dyn TranscriptionProvider>>,
+    active: Option<usize>,
+    chunking: ChunkingSettings,
+}
+
+impl ProviderRegistry {
+    /// Local mode always uses the local provider so no audio leaves the
+    /// machine. Otherwise `selected` wins when it names a registered
+    /// provider, then the backend, then whichever was registered first.
+    /// Providers reusing an earlier provider's ID are dropped.
+    pub(crate) fn new(
+        candidates: Vec<Box<dyn TranscriptionProvider>>,
+        selected: Option<&str>,
+        local_mode: bool,
+    ) -> Self {
+        let mut providers: Vec<Arc<dyn TranscriptionProvider>> = Vec::new();
+        for provider in candidates {
+            if providers.iter().any(|p| p.id() == provider.id()) {
+                eprintln!(
+                    "Ignoring transcription provider with duplicate ID '{}'",
+                    provider.id()
+                );
+                continue;
+            }
+            providers.push(Arc::from(provider));
+        }
+
+        let position = |id: &str| providers.iter().position(|p| p.id() == id);
+        let active = if local_mode {
+            position(LOCAL_PROVIDER_ID)
+        } else {
+            selected
+                .and_then(position)
+                .or_else(|| position(BACKEND_PROVIDER_ID))
+                .or(if providers.is_empty() { None } else { Some(0) })
+        };
+        Self {
+            providers,
+            active,
+            chunking: ChunkingSettings::default(),
+        }
+    }
+
+    /// Replaces the default limits for splitting long recordings.
+    pub(crate) fn with_chunking(mut self, chunking: ChunkingSettings) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    pub(crate) fn active(&self) -> Option<&Arc<dyn TranscriptionProvider>> {
+        self.active.map(|index| &self.providers[index])
+    }
+
+    /// The active provider, when it can transcribe while audio is captured.
+    pub(crate) fn streaming(&self) -> Option<Arc<dyn TranscriptionProvider>> {
+        self.active()
+            .filter(|provider| provider.supports_streaming())
+            .cloned()
+    }
+
+    pub(crate) fn list(&self) -> Vec<ProviderInfo> {
+        self.providers
+            .iter()
+            .enumerate()
+            .map(|(index, provider)| ProviderInfo {
+                id: provider.id().to_string(),
+                kind: provider.kind(),
+                active: self.active == Some(index),
+            })
+            .collect()
+    }
+
+    /// Transcribes with the active provider and records how long it took.
+    /// Only the pieces sent to the provider are encoded as WAV; recordings
+    /// over the chunk size limit are transcribed in chunks.
+    pub(crate) async fn transcribe(&self, audio: &WavPcm) -> Result<TranscriptionResponse, String> {
+        let provider = self
+            .active()
+            .cloned()
+            .ok_or_else(|| "No transcription provider available".to_string())?;
+        let started_at = Instant::now();
+        let mut chunks = chunking::split_on_silence(audio, &self.chunking);
+        let mut response = if chunks.len() > 1 {
+            self.transcribe_in_chunks(provider.clone(), chunks).await?
+        } else {
+            let wav = match chunks.pop() {
+                Some(chunk) => chunk.wav,
+                None => wav::write_pcm16(&[], audio.channels, audio.sample_rate),
+            };
+            provider
+                .transcribe(TranscriptionRequest {
+                    wav,
+                    ..TranscriptionRequest::default()
+                })
+                .await?
+        };
+        response.timing = TranscriptionTiming {
+            audio_ms: audio_ms(audio),
+            processing_ms: started_at.elapsed().as_millis() as u64,
+        };
+        response.provider = provider.id().to_string();
+        Ok(response)
+    }
+
+    /// Transcribes the chunks of a recording, at most `max_parallel` at a
+    /// time, then stitches the transcripts in order.
+    async fn transcribe_in_chunks(
+        &self,
+        provider: Arc<dyn TranscriptionProvider>,
+        mut chunks: Vec<AudioChunk>,
+    ) -> Result<TranscriptionResponse, String> {
+        let total = chunks.len();
+        println!(
+            "Transcribing {} ms of audio in {} chunks with {}.",
+            chunks
+                .last()
+                .map_or(0, |chunk| chunk.start_ms + chunk.duration_ms),
+            total,
+            provider.id()
+        );
+
+        let mut responses: Vec<Option<TranscriptionResponse>> = vec![None; total];
+        let mut running = tokio::task::JoinSet::new();
+        let mut next = 0;
+        while next < total || !running.is_empty() {
+            while next < total && running.len() < self.chunking.max_parallel.max(1) {
+                let chunk_request = TranscriptionRequest {
+                    // Only the timing is needed once the chunk is sent.
+                    wav: std::mem::take(&mut chunks[next].wav),
+                    ..TranscriptionRequest::default()
+                };
+                let provider = provider.clone();
+                let index = next;
+                running.spawn(async move { (index, provider.transcribe(chunk_request).await) });
+                next += 1;
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            // Returning early drops `running`, which cancels the other chunks.
+            let (index, result) =
+                joined.map_err(|e| format!("Chunk transcription task failed: {}", e))?;
+            let response =
+                result.map_err(|e| format!("Chunk {} of {} failed: {}", index + 1, total, e))?;
+            responses[index] = Some(response);
+        }
+        Ok(chunking::stitch(
+            &chunks,
+            responses.into_iter().flatten().collect(),
+        ))
+    }
+
+    /// Health of every registered provider, in registration order. The
+    /// checks run at the same time, so one slow provider does not hold up the
+    /// rest.
+    pub(crate) async fn health_checks(&self) -> Vec<ProviderHealth> {
+        futures_util::future::join_all(self.providers.iter().map(|provider| provider.health()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    struct FakeProvider {
+        id: &'static str,
+        kind: ProviderKind,
+    }
+
+    impl TranscriptionProvider for FakeProvider {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn kind(&self) -> ProviderKind {
+            self.kind
+        }
+
+        fn transcribe(
+            &self,
+            request: TranscriptionRequest,
+        ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+            Box::pin(async move {
+                Ok(TranscriptionResponse {
+                    text: format!("{} heard {} bytes", self.id, request.wav.len()),
+                    ..TranscriptionResponse::default()
+                })
+            })
+        }
+
+        fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+            Box::pin(async move {
+                ProviderHealth {
+                    provider: self.id.to_string(),
+                    kind: self.kind,
+                    status: HealthStatus::Ok,
+                    detail: None,
+                    latency_ms: None,
+                }
+            })
+        }
+
+        // Only the gateway transcribes while recording.
+        fn supports_streaming(&self) -> bool {
+            self.id == "gateway"
+        }
+    }
+
+    fn registry(selected: Option<&str>, local_mode: bool) -> ProviderRegistry {
+        let providers: Vec<Box<dyn TranscriptionProvider>> = vec![
+            Box::new(FakeProvider {
+                id: "gateway",
+                kind: ProviderKind::OpenAiCompatible,
+            }),
+            Box::new(FakeProvider {
+                id: "gateway",
+                kind: ProviderKind::OpenAiCompatible,
+            }),
+            Box::new(FakeProvider {
+                id: BACKEND_PROVIDER_ID,
+                kind: ProviderKind::OpenAiCompatible,
+            }),
+            Box::new(FakeProvider {
+                id: LOCAL_PROVIDER_ID,
+                kind: ProviderKind::Local,
+            }),
+        ];
+        ProviderRegistry::new(providers, selected, local_mode)
+    }
+
+    /// Answers with the length of each chunk after a short delay, tracking
+    /// how many requests run at once.
+    #[derive(Default)]
+    struct ChunkProvider {
+        running: Arc<AtomicUsize>,
+        most_running: Arc<AtomicUsize>,
+    }
+
+    impl TranscriptionProvider for ChunkProvider {
+        fn id(&self) -> &str {
+            "chunked"
+        }
+
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::OpenAiCompatible
+        }
+
+        fn transcribe(
+            &self,
+            request: TranscriptionRequest,
+        ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+            Box::pin(async move {
+                let now_running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most_running.fetch_max(now_running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                let length_ms = audio_ms(&wav::read_pcm16(&request.wav).unwrap());
+                Ok(TranscriptionResponse {
+                    text: format!("{}ms", length_ms),
+                    segments: vec![TranscriptSegment {
+                        start_ms: 0,
+                        end_ms: length_ms,
+                        text: format!("{}ms", length_ms),
+                    }],
+                    ..TranscriptionResponse::default()
+                })
+            })
+        }
+
+        fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+            Box::pin(async move {
+                ProviderHealth {
+                    provider: self.id().to_string(),
+                    kind: self.kind(),
+                    status: HealthStatus::Ok,
+                    detail: None,
+                    latency_ms: None,
+                }
+            })
+        }
+    }
+
+    fn active_id(registry: &ProviderRegistry) -> Option<&str> {
+        registry.active().map(|provider| provider.id())
+    }
+
+    #[test]
+    fn selection_falls_back_to_backend() {
+        assert_eq!(
+            active_id(&registry(Some("gateway"), false)),
+            Some("gateway")
+        );
+        assert_eq!(
+            active_id(&registry(Some("missing"), false)),
+            Some("backend")
+        );
+        assert_eq!(active_id(&registry(None, false)), Some("backend"));
+        assert!(ProviderRegistry::new(Vec::new(), None, false)
+            .active()
+            .is_none());
+    }
+
+    #[test]
+    fn local_mode_overrides_selection() {
+        let registry = registry(Some("gateway"), true);
+        assert_eq!(active_id(&registry), Some("local"));
+        let active: Vec<String> = registry
+            .list()
+            .into_iter()
+            .filter(|info| info.active)
+            .map(|info| info.id)
+            .collect();
+        assert_eq!(active, vec!["local"]);
+    }
+
+    #[test]
+    fn streaming_follows_the_active_provider() {
+        let streaming = registry(Some("gateway"), false).streaming();
+        assert_eq!(
+            streaming.map(|p| p.id().to_string()).as_deref(),
+            Some("gateway")
+        );
+        assert!(registry(Some("backend"), false).streaming().is_none());
+        assert!(registry(Some("gateway"), true).streaming().is_none());
+    }
+
+    #[tokio::test]
+    async fn response_records_provider_and_audio_length() {
+        let audio = WavPcm {
+            sample_rate: 16_000,
+            channels: 2,
+            samples: vec![0; 32_000],
+        };
+        let response = registry(Some("gateway"), false)
+            .transcribe(&audio)
+            .await
+            .unwrap();
+        assert_eq!(response.provider, "gateway");
+        // One WAV: the 44-byte header and two bytes per sample.
+        assert_eq!(response.text, "gateway heard 64044 bytes");
+        assert_eq!(response.timing.audio_ms, 1_000);
+    }
+
+    #[tokio::test]
+    async fn long_recordings_are_chunked_and_stitched_in_order() {
+        let provider = ChunkProvider::default();
+        let most_running = provider.most_running.clone();
+        let registry = ProviderRegistry::new(vec![Box::new(provider)], None, false).with_chunking(
+            ChunkingSettings {
+                // Two seconds of 16 kHz mono per chunk.
+                max_chunk_bytes: 44 + 64_000,
+                max_parallel: 2,
+                ..ChunkingSettings::default()
+            },
+        );
+        let audio = WavPcm {
+            sample_rate: 16_000,
+            channels: 1,
+            samples: vec![0; 16_000 * 9],
+        };
+
+        let response = registry.transcribe(&audio).await.unwrap();
+        assert_eq!(response.provider, "chunked");
+        assert_eq!(response.timing.audio_ms, 9_000);
+        assert!(response.segments.len() >= 5, "{:?}", response.segments);
+        assert_eq!(response.segments[0].start_ms, 0);
+        for pair in response.segments.windows(2) {
+            assert_eq!(pair[0].end_ms, pair[1].start_ms);
+        }
+        assert_eq!(response.segments.last().unwrap().end_ms, 9_000);
+        let texts: Vec<&str> = response.segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(response.text, texts.join(" "));
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn every_provider_is_health_checked() {
+        let checks = registry(None, false).health_checks().await;
+        let ids: Vec<&str> = checks.iter().map(|check| check.provider.as_str()).collect();
+        // The second "gateway" is a duplicate and was dropped.
+        assert_eq!(ids, vec!["gateway", "backend", "local"]);
+    }
+
+    /// Takes `delay` to answer a health check.
+    struct SlowHealthProvider {
+        id: &'static str,
+        delay: Duration,
+    }
+
+    impl TranscriptionProvider for SlowHealthProvider {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::OpenAiCompatible
+        }
+
+        fn transcribe(
+            &self,
+            _request: TranscriptionRequest,
+        ) -> ProviderFuture<'_, Result<TranscriptionResponse, String>> {
+            Box::pin(async { Ok(TranscriptionResponse::default()) })
+        }
+
+        fn health(&self) -> ProviderFuture<'_, ProviderHealth> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                ProviderHealth {
+                    provider: self.id.to_string(),
+                    kind: self.kind(),
+                    status: HealthStatus::Timeout,
+                    detail: None,
+                    latency_ms: Some(self.delay.as_millis() as u64),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn health_checks_run_concurrently_in_registration_order() {
+        let providers: Vec<Box<dyn TranscriptionProvider>> =
+            [("slow", 300), ("medium", 200), ("fast", 100)]
+                .into_iter()
+                .map(|(id, ms)| {
+                    Box::new(SlowHealthProvider {
+                        id,
+                        delay: Duration::from_millis(ms),
+                    }) as Box<dyn TranscriptionProvider>
+                })
+                .collect();
+        let registry = ProviderRegistry::new(providers, None, false);
+
+        let started = Instant::now();
+        let checks = registry.health_checks().await;
+        assert!(
+            started.elapsed() < Duration::from_millis(550),
+            "{:?}",
+            started.elapsed()
+        );
+        let ids: Vec<&str> = checks.iter().map(|check| check.provider.as_str()).collect();
+        assert_eq!(ids, vec!["slow", "medium", "fast"]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::ops::Range;
+
+/// Thresholds for the offline voice activity detector, stored under
+/// [`crate::settings::VAD_SETTINGS_KEY`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct VadSettings {
+    pub(crate) enabled: bool,
+    /// Analysis window length.
+    pub(crate) frame_ms: u32,
+    /// Frames quieter than this are never voiced, whatever the noise floor.
+    pub(crate) min_level_dbfs: f32,
+    /// How far above the estimated noise floor a frame must be to count as voiced.
+    pub(crate) noise_margin_db: f32,
+    /// Total voiced time required before a recording is considered speech.
+    pub(crate) min_speech_ms: u32,
+    /// Audio kept before the first and after the last voiced frame.
+    pub(crate) padding_ms: u32,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frame_ms: 20,
+            min_level_dbfs: -50.0,
+            noise_margin_db: 10.0,
+            min_speech_ms: 200,
+            padding_ms: 250,
+        }
+    }
+}
+
+/// RMS level of `frame` in dB relative to full scale.
+pub(crate) fn frame_dbfs(frame: &[i16]) -> f32 {
+    if frame.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let sum_squares: f64 = frame.iter().map(|&s| f64::from(s) * f64::from(s)).sum();
+    let rms = (sum_squares / frame.len() as f64).sqrt() / f64::from(i16::MAX);
+    (20.0 * rms.max(1e-9).log10()) as f32
+}
+
+/// Finds the span of mono `samples` that contains speech, padded on both ends.
+/// Returns `None` when less than `min_speech_ms` of voiced audio is present.
+pub(crate) fn detect_speech(
+    samples: &[i16],
+    sample_rate: u32,
+    settings: &VadSettings,
+) -> Option<Range<usize>> {
+    let frame_len = (sample_rate as usize * settings.frame_ms.max(1) as usize / 1000).max(1);
+    let levels: Vec<f32> = samples.chunks(frame_len).map(frame_dbfs).collect();
+    if levels.is_empty() {
+        return None;
+    }
+
+    // The quietest tenth of the recording approximates the background level.
+    // Speech rises and falls with every syllable, so a recording whose loud
+    // end stays within the margin of that floor is steady noise such as a
+    // fan or hum, however loud. Capping against the loud end keeps recordings
+    // with no pauses at all from putting every frame below the threshold.
+    let mut sorted = levels.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = sorted[sorted.len() / 10];
+    let loud_level = sorted[sorted.len() * 95 / 100];
+    if loud_level - noise_floor < settings.noise_margin_db {
+        return None;
+    }
+    let threshold = (noise_floor + settings.noise_margin_db)
+        .min(loud_level - settings.noise_margin_db / 2.0)
+        .max(settings.min_level_dbfs);
+
+    let voiced: Vec<usize> = levels
+        .iter()
+        .enumerate()
+        .filter(|(_, &level)| level >= threshold)
+        .map(|(index, _)| index)
+        .collect();
+
+    let voiced_ms = voiced.len() as u64 * u64::from(settings.frame_ms.max(1));
+    if voiced_ms < u64::from(settings.min_speech_ms) {
+        return None;
+    }
+
+    let first = *voiced.first()?;
+    let last = *voiced.last()?;
+    let padding = sample_rate as usize * settings.padding_ms as usize / 1000;
+    let start = (first * frame_len).saturating_sub(padding);
+    let end = ((last + 1) * frame_len + padding).min(samples.len());
+
+    Some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+
+    /// Deterministic white noise at roughly `level_dbfs`.
+    fn noise(level_dbfs: f32, len: usize) -> Vec<i16> {
+        let amplitude = f32::from(i16::MAX) * 10f32.powf(level_dbfs / 20.0) * 3f32.sqrt();
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let uniform = (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0;
+                (amplitude * uniform) as i16
+            })
+            .collect()
+    }
+
+    /// A 200 Hz tone whose loudness rises and falls four times a second,
+    /// like syllables.
+    fn speech(len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                let envelope = (2.0 * std::f32::consts::PI * 4.0 * t).sin().abs();
+                (12_000.0 * envelope * (2.0 * std::f32::consts::PI * 200.0 * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn silence_has_no_speech() {
+        assert_eq!(
+            detect_speech(&vec![0; 32_000], RATE, &VadSettings::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn steady_noise_is_not_speech() {
+        let settings = VadSettings::default();
+        // Well above the -50 dBFS floor, like a loud fan.
+        assert_eq!(detect_speech(&noise(-30.0, 48_000), RATE, &settings), None);
+        assert_eq!(detect_speech(&noise(-45.0, 48_000), RATE, &settings), None);
+    }
+
+    #[test]
+    fn leading_and_trailing_silence_is_trimmed() {
+        let settings = VadSettings::default();
+        let mut samples = noise(-60.0, RATE as usize);
+        samples.extend(speech(RATE as usize));
+        samples.extend(noise(-60.0, RATE as usize));
+
+        let speech_range = detect_speech(&samples, RATE, &settings).unwrap();
+        let padding = (RATE * settings.padding_ms / 1000) as usize;
+        let frame = (RATE * settings.frame_ms / 1000) as usize;
+        // Speech runs from 1 s to 2 s; its quiet first and last frames may
+        // fall below the threshold.
+        let starts_near = RATE as usize - padding;
+        let ends_near = 2 * RATE as usize + padding;
+        assert!(
+            (starts_near..=starts_near + 3 * frame).contains(&speech_range.start),
+            "{:?}",
+            speech_range
+        );
+        assert!(
+            (ends_near - 3 * frame..=ends_near).contains(&speech_range.end),
+            "{:?}",
+            speech_range
+        );
+    }
+
+    #[test]
+    fn speech_over_steady_noise_is_found() {
+        let settings = VadSettings::default();
+        let mut samples = noise(-35.0, 3 * RATE as usize);
+        for (sample, voice) in samples[RATE as usize..]
+            .iter_mut()
+            .zip(speech(RATE as usize))
+        {
+            *sample = sample.saturating_add(voice);
+        }
+        let speech_range = detect_speech(&samples, RATE, &settings).unwrap();
+        assert!(speech_range.start < RATE as usize && speech_range.end > 2 * RATE as usize - 4_000);
+        assert!(speech_range.len() < 2 * RATE as usize, "{:?}", speech_range);
+    }
+}
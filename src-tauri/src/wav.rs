@@ -0,0 +1,125 @@
+//! Minimal RIFF/WAVE reading and writing for 16-bit PCM.
+
+/// Decoded 16-bit PCM audio, interleaved when `channels > 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WavPcm {
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u16,
+    pub(crate) samples: Vec<i16>,
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Parses a WAV file holding 16-bit integer PCM. Unknown chunks are skipped.
+pub(crate) fn read_pcm16(bytes: &[u8]) -> Result<WavPcm, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF/WAVE file".to_string());
+    }
+
+    let mut format: Option<(u16, u32)> = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let chunk_id = &bytes[position..position + 4];
+        let chunk_size = read_u32(bytes, position + 4).unwrap_or(0) as usize;
+        let body_start = position + 8;
+        let body_end = (body_start + chunk_size).min(bytes.len());
+        let body = &bytes[body_start..body_end];
+
+        match chunk_id {
+            b"fmt " => {
+                let audio_format = read_u16(body, 0).ok_or("Truncated fmt chunk")?;
+                let channels = read_u16(body, 2).ok_or("Truncated fmt chunk")?;
+                let sample_rate = read_u32(body, 4).ok_or("Truncated fmt chunk")?;
+                let bits_per_sample = read_u16(body, 14).ok_or("Truncated fmt chunk")?;
+                // 0xFFFE is WAVE_FORMAT_EXTENSIBLE, which still carries plain PCM here.
+                if !matches!(audio_format, 1 | 0xFFFE) || bits_per_sample != 16 {
+                    return Err(format!(
+                        "Unsupported WAV encoding: format {}, {} bits per sample",
+                        audio_format, bits_per_sample
+                    ));
+                }
+                if channels == 0 || sample_rate == 0 {
+                    return Err("WAV file declares no channels or sample rate".to_string());
+                }
+                format = Some((channels, sample_rate));
+            }
+            b"data" => {
+                let (channels, sample_rate) =
+                    format.ok_or("WAV data chunk appears before fmt chunk")?;
+                let samples = body
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                return Ok(WavPcm {
+                    sample_rate,
+                    channels,
+                    samples,
+                });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length.
+        position = body_start + chunk_size + (chunk_size & 1);
+    }
+
+    Err("WAV file has no data chunk".to_string())
+}
+
+/// Encodes interleaved `pcm` as a 16-bit PCM WAV file.
+pub(crate) fn write_pcm16(pcm: &[i16], channels: u16, sample_rate: u32) -> Vec<u8> {
+    let bits_per_sample: u16 = 16;
+    let bytes_per_sample = bits_per_sample / 8;
+    let block_align = channels * bytes_per_sample;
+    let byte_rate = sample_rate * u32::from(block_align);
+    let data_bytes = pcm.len() * usize::from(bytes_per_sample);
+    // Sizes past what the 32-bit fields hold are written as the maximum, as
+    // streaming writers do; readers then take the data to the end of the file.
+    let data_size = u32::try_from(data_bytes).unwrap_or(u32::MAX);
+
+    if data_size == 0 {
+        println!("Warning: Creating WAV from empty PCM data.");
+    }
+
+    let mut wav_data = Vec::with_capacity(44 + data_bytes);
+    wav_data.extend_from_slice(b"RIFF");
+    wav_data.extend_from_slice(&data_size.saturating_add(36).to_le_bytes());
+    wav_data.extend_from_slice(b"WAVE");
+    wav_data.extend_from_slice(b"fmt ");
+    wav_data.extend_from_slice(&16u32.to_le_bytes());
+    wav_data.extend_from_slice(&1u16.to_le_bytes());
+    wav_data.extend_from_slice(&channels.to_le_bytes());
+    wav_data.extend_from_slice(&sample_rate.to_le_bytes());
+    wav_data.extend_from_slice(&byte_rate.to_le_bytes());
+    wav_data.extend_from_slice(&block_align.to_le_bytes());
+    wav_data.extend_from_slice(&bits_per_sample.to_le_bytes());
+    wav_data.extend_from_slice(b"data");
+    wav_data.extend_from_slice(&data_size.to_le_bytes());
+    wav_data.extend(pcm.iter().flat_map(|sample| sample.to_le_bytes()));
+    wav_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_wav_reads_back() {
+        let pcm = vec![0, 1, -1, i16::MAX, i16::MIN, 1234];
+        let decoded = read_pcm16(&write_pcm16(&pcm, 2, 22_050)).unwrap();
+        assert_eq!(
+            decoded,
+            WavPcm {
+                sample_rate: 22_050,
+                channels: 2,
+                samples: pcm,
+            }
+        );
+    }
+}
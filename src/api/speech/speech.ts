@@ -23,6 +23,40 @@ export async function transcribeAudio(audio: File) {
   return await res.json();
 }
 
+/**
+ * Transcribe the chunks of a recording, at most `maxParallel` at a time
+ * @param chunks - Audio files in recording order
+ * @param maxParallel - How many requests may run at once
+ * @returns A promise resolving to the joined transcription, or the first
+ * failed response
+ */
+export async function transcribeAudioChunks(
+  chunks: File[],
+  maxParallel: number
+) {
+  const results = new Array(chunks.length);
+  let next = 0;
+  const worker = async () => {
+    while (next < chunks.length) {
+      const index = next++;
+      results[index] = await transcribeAudio(chunks[index]);
+    }
+  };
+  const workers = Math.min(Math.max(maxParallel, 1), chunks.length);
+  await Promise.all(Array.from({ length: workers }, worker));
+
+  const failed = results.find((result) => !("text" in result));
+  if (failed) {
+    return failed;
+  }
+  return {
+    text: results
+      .map((result) => result.text.trim())
+      .filter((text) => text)
+      .join(" "),
+  };
+}
+
 /**
  * Generate speech from text using the backend API
  * @param text - The text to convert to speech
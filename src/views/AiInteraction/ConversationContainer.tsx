@@ -8,6 +8,7 @@ import {
 import { useChatRuntime } from "@assistant-ui/react-ai-sdk";
 import { Assistant } from "./assistant";
 import {
+  PartialTranscript,
   SendMessageFn,
   SetTranscriptionStatusFn,
 } from "./hooks/useAiInteraction";
@@ -22,6 +23,7 @@ export const ConversationContainer: React.FC<{
   isNewChat: boolean;
   sendMessageRef: React.MutableRefObject<SendMessageFn | null>;
   setTranscriptionStatusRef: React.MutableRefObject<SetTranscriptionStatusFn | null>;
+  partialTranscript: PartialTranscript | null;
   refreshConversations: () => Promise<void>;
   navigate: (path: string, options?: any) => void;
 }> = ({
@@ -31,6 +33,7 @@ export const ConversationContainer: React.FC<{
   isNewChat,
   sendMessageRef,
   setTranscriptionStatusRef,
+  partialTranscript,
   refreshConversations,
   navigate,
 }) => {
@@ -72,6 +75,7 @@ export const ConversationContainer: React.FC<{
       <Assistant
         sendMessageRef={sendMessageRef}
         setTranscriptionStatusRef={setTranscriptionStatusRef}
+        partialTranscript={partialTranscript}
       />
     </AssistantRuntimeProvider>
   );
@@ -2,6 +2,7 @@ import { useThreadRuntime } from "@assistant-ui/react"; // Import AssistantRunti
 import { Thread } from "./components/assistant-ui/thread";
 import { MutableRefObject, useEffect, useState } from "react"; // Import useRef
 import {
+  PartialTranscript,
   RecorderState,
   SendMessageFn,
   SetTranscriptionStatusFn,
@@ -12,11 +13,13 @@ import { useTextToSpeech } from "./hooks/useTextToSpeech"; // Import the new hoo
 interface AssistantProps {
   sendMessageRef: MutableRefObject<SendMessageFn | null>;
   setTranscriptionStatusRef: MutableRefObject<SetTranscriptionStatusFn | null>;
+  partialTranscript: PartialTranscript | null;
 }
 
 export const Assistant = ({
   sendMessageRef,
   setTranscriptionStatusRef,
+  partialTranscript,
 }: AssistantProps) => {
   const { append } = useThreadRuntime(); // Only need append here now
   // Get all returned values from the hook
@@ -69,6 +72,7 @@ export const Assistant = ({
     <div className="flex-1 w-full h-full px-2 pt-2">
       <Thread
         recorderState={recorderState}
+        partialTranscript={partialTranscript}
         isPlayingAudio={isPlayingAudio}
         stopAudioPlayback={stopAudioPlayback}
         playAudioForText={playAudioForText} // Pass down the new function
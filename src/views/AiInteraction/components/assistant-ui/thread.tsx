@@ -23,7 +23,10 @@ import { cn } from "../../../../lib/utils";
 import { Button } from "../../../../components/ui/button";
 import { MarkdownText } from "./markdown-text";
 import { TooltipIconButton } from "./tooltip-icon-button";
-import { RecorderState } from "../../hooks/useAiInteraction";
+import {
+  PartialTranscript,
+  RecorderState,
+} from "../../hooks/useAiInteraction";
 import RecordingIndicator from "../../../../components/RecordingIndicator";
 import TranscribingIndicator from "../../../../components/TranscribingIndicator";
 import {
@@ -37,6 +40,7 @@ type PlayAudioForTextFn = (text: string) => Promise<void>;
 
 interface ThreadProps {
   recorderState?: RecorderState;
+  partialTranscript?: PartialTranscript | null;
   isPlayingAudio?: boolean;
   stopAudioPlayback?: () => void;
   playAudioForText?: PlayAudioForTextFn; // Add the new prop
@@ -44,6 +48,7 @@ interface ThreadProps {
 
 export const Thread: FC<ThreadProps> = ({
   recorderState = "idle",
+  partialTranscript = null,
   isPlayingAudio = false,
   stopAudioPlayback = () => {},
   playAudioForText = async () => {}, // Default async no-op
@@ -86,6 +91,16 @@ export const Thread: FC<ThreadProps> = ({
             {recorderState === "recording" && <RecordingIndicator />}
             {recorderState === "transcribing" && <TranscribingIndicator />}
 
+            {/* Live words while speaking, until the final transcript arrives */}
+            {partialTranscript && recorderState !== "idle" && (
+              <p className="w-full px-4 text-center text-sm">
+                {partialTranscript.stable}{" "}
+                <span className="italic text-muted-foreground">
+                  {partialTranscript.unstable}
+                </span>
+              </p>
+            )}
+
             {/* Idle State: Show Composer */}
             {recorderState === "idle" && (
               <div className="flex items-end w-full gap-2">
@@ -4,10 +4,53 @@ import { useRef, useEffect, useState } from "react";
 import { useTranscription } from "./useTranscription";
 import { useClipboardPaste } from "./useClipboard";
 
+// Quality measurements the backend attaches to each finished recording
+export interface RecordingDiagnostics {
+  duration_ms: number;
+  rms_dbfs: number;
+  peak_dbfs: number;
+  clipped_ratio: number;
+  dc_offset: number;
+  issues: ("digital_silence" | "too_quiet" | "clipping" | "dc_offset")[];
+  usable: boolean;
+  warning: string | null;
+}
+
 // Interface for the audio data payload from backend
 interface AudioDataPayload {
-  data: number[];
+  // WAV files in recording order; long recordings are cut at pauses so each
+  // upload stays under the size limit
+  chunks: number[][];
+  // How many chunks may be transcribed at once
+  maxParallel: number;
+  isClipboardMode: boolean;
+  diagnostics?: RecordingDiagnostics;
+  // Identifies the backend recording session the audio came from
+  sessionId?: number;
+}
+
+// A recording the backend transcribed itself (clipboard mode and local mode)
+interface TranscriptionCompletedPayload {
+  text: string;
   isClipboardMode: boolean;
+  diagnostics?: RecordingDiagnostics;
+  sessionId?: number;
+}
+
+// Live transcript of a recording in progress, when streaming is enabled
+export interface PartialTranscript {
+  // Text the streaming provider will not revise
+  stable: string;
+  // Its current guess at the words after the stable text
+  unstable: string;
+  sessionId?: number;
+}
+
+// Reported by the backend when transcribing or pasting a recording fails
+interface ProcessingErrorPayload {
+  stage: string;
+  message: string;
+  sessionId?: number;
 }
 
 export type RecorderState = "idle" | "recording" | "transcribing";
@@ -18,11 +61,18 @@ export type SetTranscriptionStatusFn = (isTranscribing: RecorderState) => void;
 export default function useAiInteraction() {
   const unlistenStateRef = useRef<UnlistenFn | null>(null); // Ref for state listener
   const unlistenAudioDataRef = useRef<UnlistenFn | null>(null); // Ref for audio data listener
+  const unlistenQualityRef = useRef<UnlistenFn | null>(null); // Ref for recording quality warnings
+  const unlistenNoSpeechRef = useRef<UnlistenFn | null>(null); // Ref for recordings without speech
+  const unlistenProcessingErrorRef = useRef<UnlistenFn | null>(null); // Ref for backend processing errors
+  const unlistenTranscriptRef = useRef<UnlistenFn | null>(null); // Ref for backend transcripts
+  const unlistenPartialRef = useRef<UnlistenFn | null>(null); // Ref for live partial transcripts
   const sendMessageRef = useRef<SendMessageFn | null>(null);
   const setTranscriptionStatusRef = useRef<SetTranscriptionStatusFn | null>(
     null
   );
   const [errorMessage, setErrorMessage] = useState<string | null>(null);
+  const [partialTranscript, setPartialTranscript] =
+    useState<PartialTranscript | null>(null);
   const [currentMode, setCurrentMode] = useState<"normal" | "clipboard" | null>(
     null
   );
@@ -38,6 +88,7 @@ export default function useAiInteraction() {
       unlistenAudioDataRef.current = await listen<AudioDataPayload>(
         "audio_data_available",
         async (event) => {
+          setPartialTranscript(null);
           // Extract the data and metadata early so it's available in both try and catch blocks
           const isClipboardMode = event.payload.isClipboardMode || false;
 
@@ -45,14 +96,13 @@ export default function useAiInteraction() {
             // Update the current mode
             setCurrentMode(isClipboardMode ? "clipboard" : "normal");
 
-            // Convert the array to Uint8Array
-            const wavData = new Uint8Array(event.payload.data || event.payload);
-
-            // Create a Blob and File object from the audio data
-            const wavBlob = new Blob([wavData], { type: "audio/wav" });
-            const wavFile = new File([wavBlob], "recording.wav", {
-              type: "audio/wav",
-            });
+            // Create a File object for each chunk of audio data
+            const wavFiles = event.payload.chunks.map(
+              (chunk, index) =>
+                new File([new Uint8Array(chunk)], `recording-${index}.wav`, {
+                  type: "audio/wav",
+                })
+            );
 
             // Only update UI status for normal mode, not clipboard mode
             if (!isClipboardMode && setTranscriptionStatusRef.current) {
@@ -61,9 +111,10 @@ export default function useAiInteraction() {
 
             // Send to transcription API using the mutation
             try {
-              const transcriptionText = await transcriptionMutation.mutateAsync(
-                wavFile
-              );
+              const transcriptionText = await transcriptionMutation.mutateAsync({
+                chunks: wavFiles,
+                maxParallel: event.payload.maxParallel,
+              });
               if (!("text" in transcriptionText)) {
               } else {
                 if (isClipboardMode) {
@@ -138,6 +189,9 @@ export default function useAiInteraction() {
           if (setTranscriptionStatusRef.current) {
             setTranscriptionStatusRef.current(event.payload);
           }
+          if (event.payload === "recording") {
+            setPartialTranscript(null);
+          }
         }
       );
     };
@@ -152,6 +206,122 @@ export default function useAiInteraction() {
     };
   }, []);
 
+  // Triggered when a recording was dropped as unusable instead of transcribed
+  useEffect(() => {
+    const setupQualityListener = async () => {
+      unlistenQualityRef.current = await listen<RecordingDiagnostics>(
+        "recording_quality_warning",
+        (event) => {
+          setErrorMessage(
+            event.payload.warning ?? "The recording could not be used."
+          );
+        }
+      );
+    };
+
+    setupQualityListener();
+
+    return () => {
+      if (unlistenQualityRef.current) {
+        unlistenQualityRef.current();
+        unlistenQualityRef.current = null;
+      }
+    };
+  }, []);
+
+  // Triggered when voice activity detection found no speech in a recording
+  useEffect(() => {
+    const setupNoSpeechListener = async () => {
+      unlistenNoSpeechRef.current = await listen("recording_no_speech", () => {
+        setPartialTranscript(null);
+      });
+    };
+
+    setupNoSpeechListener();
+
+    return () => {
+      if (unlistenNoSpeechRef.current) {
+        unlistenNoSpeechRef.current();
+        unlistenNoSpeechRef.current = null;
+      }
+    };
+  }, []);
+
+  // Triggered when the backend transcribed a chat recording itself (any
+  // provider but the backend)
+  useEffect(() => {
+    const appWindow = getCurrentWindow();
+    const setupTranscriptListener = async () => {
+      unlistenTranscriptRef.current =
+        await listen<TranscriptionCompletedPayload>(
+          "transcription_completed",
+          (event) => {
+            setPartialTranscript(null);
+            // Clipboard transcripts are pasted by the backend
+            if (event.payload.isClipboardMode || !event.payload.text.trim()) {
+              return;
+            }
+            if (sendMessageRef.current) {
+              appWindow.setFocus();
+              sendMessageRef.current(event.payload.text);
+            }
+          }
+        );
+    };
+
+    setupTranscriptListener();
+
+    return () => {
+      if (unlistenTranscriptRef.current) {
+        unlistenTranscriptRef.current();
+        unlistenTranscriptRef.current = null;
+      }
+    };
+  }, []);
+
+  // Triggered while the user is still speaking, when streaming is enabled
+  useEffect(() => {
+    const setupPartialListener = async () => {
+      unlistenPartialRef.current = await listen<PartialTranscript>(
+        "transcript_partial",
+        (event) => {
+          setPartialTranscript(event.payload);
+        }
+      );
+    };
+
+    setupPartialListener();
+
+    return () => {
+      if (unlistenPartialRef.current) {
+        unlistenPartialRef.current();
+        unlistenPartialRef.current = null;
+      }
+    };
+  }, []);
+
+  // Triggered when the backend fails to transcribe or paste a recording
+  useEffect(() => {
+    const setupProcessingErrorListener = async () => {
+      unlistenProcessingErrorRef.current = await listen<ProcessingErrorPayload>(
+        "processing_error",
+        (event) => {
+          setPartialTranscript(null);
+          setErrorMessage(event.payload.message);
+        }
+      );
+    };
+
+    setupProcessingErrorListener();
+
+    return () => {
+      if (unlistenProcessingErrorRef.current) {
+        unlistenProcessingErrorRef.current();
+        unlistenProcessingErrorRef.current = null;
+      }
+    };
+  }, []);
+
   // Triggered when user closes the window manually
   // We prevent window close and just hide it so that user can use the shortcut next time
   useEffect(() => {
@@ -186,6 +356,7 @@ export default function useAiInteraction() {
     sendMessageRef,
     setTranscriptionStatusRef,
     errorMessage,
+    partialTranscript,
     // Only show transcription UI indicator for normal mode operations
     isTranscribing:
       (currentMode === "normal" || currentMode === null) &&
@@ -1,13 +1,19 @@
-import { transcribeAudio } from "../../../api/speech/speech";
+import { transcribeAudioChunks } from "../../../api/speech/speech";
 import { useMutation } from "@tanstack/react-query";
 
 /**
  * React Query hook for transcribing audio
- * @returns A mutation hook for transcribing audio files
+ * @returns A mutation hook for transcribing the chunks of a recording
  */
 export function useTranscription() {
   return useMutation({
-    mutationFn: (audioFile: File) => transcribeAudio(audioFile),
+    mutationFn: ({
+      chunks,
+      maxParallel,
+    }: {
+      chunks: File[];
+      maxParallel: number;
+    }) => transcribeAudioChunks(chunks, maxParallel),
     onError: (error) => {
       console.error("Transcription error:", error);
     },
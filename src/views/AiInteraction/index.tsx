@@ -18,8 +18,12 @@ import MurmurLoader from "@/components/ui/MurmurLoader";
 const AiInteractionWindow: React.FC = () => {
   const { id: chatId } = useParams<{ id: string }>();
   const navigate = useNavigate();
-  const { sendMessageRef, setTranscriptionStatusRef, errorMessage } =
-    useAiInteraction();
+  const {
+    sendMessageRef,
+    setTranscriptionStatusRef,
+    errorMessage,
+    partialTranscript,
+  } = useAiInteraction();
 
   if (errorMessage) {
     toast.error(errorMessage, {
@@ -80,6 +84,7 @@ const AiInteractionWindow: React.FC = () => {
       isNewChat={isNewChat}
       sendMessageRef={sendMessageRef}
       setTranscriptionStatusRef={setTranscriptionStatusRef}
+      partialTranscript={partialTranscript}
       refreshConversations={refreshConversations}
       navigate={navigate}
     />
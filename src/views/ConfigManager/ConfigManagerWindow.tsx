@@ -40,6 +40,7 @@ import {
   RefreshCcw,
 } from "lucide-react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 type PermissionStatus = "granted" | "denied" | "unknown" | "checking";
 type ConnectionStatus = "connected" | "disconnected" | "testing" | "error";
@@ -55,6 +56,29 @@ interface AudioDevice {
   is_default: boolean;
 }
 
+// Payload of the backend's audio_devices_changed event
+interface AudioDevicesChanged {
+  added: { id: string; name: string }[];
+  removed: { id: string; name: string }[];
+}
+
+// Result of the backend's check_transcription_providers command
+interface ProviderHealth {
+  provider: string;
+  kind: "open_ai_compatible" | "local";
+  status:
+    | "ok"
+    | "misconfigured"
+    | "unavailable"
+    | "unauthorized"
+    | "server_error"
+    | "timeout"
+    | "connection_failed"
+    | "network_error";
+  detail: string | null;
+  latency_ms: number | null;
+}
+
 export const ConfigManagerWindow: React.FC = () => {
   const [settings, setSettings] = useState<AppSettings>({
     use_local_mode: false,
@@ -69,9 +93,11 @@ export const ConfigManagerWindow: React.FC = () => {
   const [connectionStatus, setConnectionStatus] =
     useState<ConnectionStatus>("disconnected");
   const [testingConnection, setTestingConnection] = useState(false);
+  const [providerHealth, setProviderHealth] = useState<ProviderHealth[]>([]);
   const [audioDevices, setAudioDevices] = useState<AudioDevice[]>([]);
   const [selectedAudioDevice, setSelectedAudioDevice] = useState<string>("");
   const [loadingAudioDevices, setLoadingAudioDevices] = useState(false);
+  const [inputGainDb, setInputGainDb] = useState(0);
 
   useEffect(() => {
     const loadSettings = async () => {
@@ -85,6 +111,20 @@ export const ConfigManagerWindow: React.FC = () => {
     checkAllPermissions();
     loadAudioDevices();
   }, []);
+
+  // The backend watches for devices being plugged in or removed
+  useEffect(() => {
+    const unlisten = listen<AudioDevicesChanged>(
+      "audio_devices_changed",
+      (event) => {
+        console.log("Audio input devices changed:", event.payload);
+        loadAudioDevices();
+      }
+    );
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
   const loadAudioDevices = async () => {
     setLoadingAudioDevices(true);
     try {
@@ -102,7 +142,7 @@ export const ConfigManagerWindow: React.FC = () => {
         // Default to the default device if available
         const defaultDevice = devices.find((device) => device.is_default);
         if (defaultDevice) {
-          setSelectedAudioDevice(defaultDevice.name);
+          setSelectedAudioDevice(defaultDevice.id);
         }
       }
     } catch (error) {
@@ -112,10 +152,32 @@ export const ConfigManagerWindow: React.FC = () => {
     }
   };
 
-  const handleAudioDeviceChange = async (deviceName: string) => {
+  useEffect(() => {
+    if (!selectedAudioDevice) return;
+    invoke<number>("get_audio_device_gain", { deviceId: selectedAudioDevice })
+      .then(setInputGainDb)
+      .catch((error) => {
+        console.error("Error getting input gain:", error);
+        setInputGainDb(0);
+      });
+  }, [selectedAudioDevice]);
+
+  const handleInputGainChange = async (gainDb: number) => {
+    setInputGainDb(gainDb);
+    try {
+      await invoke("set_audio_device_gain", {
+        deviceId: selectedAudioDevice,
+        gainDb,
+      });
+    } catch (error) {
+      console.error("Error setting input gain:", error);
+    }
+  };
+
+  const handleAudioDeviceChange = async (deviceId: string) => {
     try {
-      await invoke("set_selected_audio_device", { deviceName });
-      setSelectedAudioDevice(deviceName);
+      await invoke("set_selected_audio_device", { deviceId });
+      setSelectedAudioDevice(deviceId);
     } catch (error) {
       console.error("Error setting audio device:", error);
     }
@@ -183,10 +245,19 @@ export const ConfigManagerWindow: React.FC = () => {
     setConnectionStatus("testing");
 
     try {
-      const result = await invoke<string>("test_backend_connection", {
-        url: settings.backend_url,
-      });
+      const [result, health] = await Promise.all([
+        invoke<string>("test_backend_connection", {
+          url: settings.backend_url,
+        }),
+        invoke<ProviderHealth[]>("check_transcription_providers").catch(
+          (error) => {
+            console.error("Error checking transcription providers:", error);
+            return [];
+          }
+        ),
+      ]);
       setConnectionStatus(result === "connected" ? "connected" : "error");
+      setProviderHealth(health);
     } catch (error) {
       console.error("Error testing backend connection:", error);
       setConnectionStatus("error");
@@ -392,7 +463,7 @@ export const ConfigManagerWindow: React.FC = () => {
                         </SelectTrigger>
                         <SelectContent>
                           {audioDevices.map((device) => (
-                            <SelectItem key={device.id} value={device.name}>
+                            <SelectItem key={device.id} value={device.id}>
                               <div className="flex items-center space-x-2">
                                 <span>{device.name}</span>
                                 {device.is_default && (
@@ -407,6 +478,29 @@ export const ConfigManagerWindow: React.FC = () => {
                       </Select>
                     )}
                   </div>
+                  {selectedAudioDevice && (
+                    <div className="flex items-center space-x-3">
+                      <Label htmlFor="input-gain" className="text-xs">
+                        Input gain
+                      </Label>
+                      <input
+                        id="input-gain"
+                        type="range"
+                        min={-30}
+                        max={30}
+                        step={1}
+                        value={inputGainDb}
+                        onChange={(e) =>
+                          handleInputGainChange(Number(e.target.value))
+                        }
+                        className="flex-1"
+                      />
+                      <span className="text-xs text-muted-foreground w-14 text-right">
+                        {inputGainDb > 0 ? "+" : ""}
+                        {inputGainDb} dB
+                      </span>
+                    </div>
+                  )}
                 </div>
               )}
             </div>
@@ -492,7 +586,8 @@ export const ConfigManagerWindow: React.FC = () => {
               <div>
                 <h3 className="font-medium">Local Mode</h3>
                 <p className="text-sm text-muted-foreground">
-                  Use a local backend instance instead of remote server
+                  Transcribe recordings on this device; no audio is
+                  uploaded. Needs a build with the on-device engine.
                 </p>
               </div>
             </div>
@@ -549,10 +644,35 @@ export const ConfigManagerWindow: React.FC = () => {
               </Button>
             </div>
 
+            {providerHealth.length > 0 && (
+              <div className="space-y-1">
+                <Label>Transcription Providers</Label>
+                {providerHealth.map((health) => (
+                  <div
+                    key={health.provider}
+                    className="flex items-center space-x-2 text-sm"
+                  >
+                    {health.status === "ok" ? (
+                      <CheckCircle className="h-4 w-4 text-green-500" />
+                    ) : (
+                      <XCircle className="h-4 w-4 text-red-500" />
+                    )}
+                    <span className="font-medium">{health.provider}</span>
+                    <span className="text-muted-foreground">
+                      {health.status.replace(/_/g, " ")}
+                      {health.latency_ms !== null &&
+                        ` · ${health.latency_ms} ms`}
+                      {health.detail && ` · ${health.detail}`}
+                    </span>
+                  </div>
+                ))}
+              </div>
+            )}
+
             <p className="text-sm text-muted-foreground">
               Enter the full URL of your Murmur backend API endpoint.
               {settings.use_local_mode
-                ? " Local mode is enabled - ensure your local server is running."
+                ? " Local mode is enabled - recordings are transcribed on this device."
                 : " Make sure the server is accessible from your network."}
             </p>
           </div>